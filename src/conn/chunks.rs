@@ -0,0 +1,278 @@
+use futures_util::stream::Stream;
+use hyper::body::Bytes;
+use pin_project::pin_project;
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::time::Sleep;
+
+/// Splits chunks of `stream` larger than `max_chunk_size` into multiple chunks. Splitting
+/// doesn't copy the data.
+///
+/// Use [`Rechunk::coalesce`] to also merge small chunks together.
+///
+/// # Panics
+///
+/// Panics if `max_chunk_size` is `0`.
+pub fn rechunk<S, E>(stream: S, max_chunk_size: usize) -> Rechunk<S>
+where
+    S: Stream<Item = Result<Bytes, E>>,
+{
+    assert!(max_chunk_size > 0, "max_chunk_size must be greater than 0");
+    Rechunk {
+        stream,
+        max_chunk_size,
+        min_chunk_size: 0,
+        flush_interval: None,
+        flush_timer: None,
+        pending: Bytes::new(),
+        buf: Vec::new(),
+        done: false,
+    }
+}
+
+/// Stream adapter returned by [`rechunk`].
+#[pin_project]
+#[derive(Debug)]
+pub struct Rechunk<S> {
+    #[pin]
+    stream: S,
+    max_chunk_size: usize,
+    min_chunk_size: usize,
+    flush_interval: Option<Duration>,
+    flush_timer: Option<Pin<Box<Sleep>>>,
+    /// Data received from the inner stream that was not yet emitted.
+    pending: Bytes,
+    /// Small chunks being merged together.
+    buf: Vec<u8>,
+    done: bool,
+}
+
+impl<S> Rechunk<S> {
+    /// Merges chunks smaller than `min_chunk_size` until they reach at least that size. Merged
+    /// data is emitted early if no new data arrives within `flush_interval` so that a slow
+    /// stream is not held back indefinitely.
+    ///
+    /// `min_chunk_size` is capped at the maximum chunk size.
+    ///
+    /// # Panics
+    ///
+    /// The flush timer is a tokio timer, polling the stream panics if it is not done within a
+    /// tokio runtime with the time driver enabled.
+    pub fn coalesce(mut self, min_chunk_size: usize, flush_interval: Duration) -> Self {
+        self.min_chunk_size = min_chunk_size.min(self.max_chunk_size);
+        self.flush_interval = Some(flush_interval);
+        self
+    }
+}
+
+impl<S, E> Stream for Rechunk<S>
+where
+    S: Stream<Item = Result<Bytes, E>>,
+{
+    type Item = Result<Bytes, E>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        loop {
+            if !this.pending.is_empty() {
+                let n = (*this.max_chunk_size - this.buf.len()).min(this.pending.len());
+                let chunk = this.pending.split_to(n);
+
+                if this.buf.is_empty() && chunk.len() >= *this.min_chunk_size {
+                    return Poll::Ready(Some(Ok(chunk)));
+                }
+
+                if this.buf.is_empty() {
+                    if let Some(interval) = this.flush_interval {
+                        *this.flush_timer = Some(Box::pin(tokio::time::sleep(*interval)));
+                    }
+                }
+                this.buf.extend_from_slice(&chunk);
+                if this.buf.len() >= *this.min_chunk_size {
+                    return Poll::Ready(Some(Ok(take_buf(this.buf, this.flush_timer))));
+                }
+                continue;
+            }
+
+            if *this.done {
+                return Poll::Ready(None);
+            }
+
+            match this.stream.as_mut().poll_next(cx) {
+                Poll::Ready(Some(Ok(chunk))) => *this.pending = chunk,
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+                Poll::Ready(None) => {
+                    *this.done = true;
+                    if this.buf.is_empty() {
+                        return Poll::Ready(None);
+                    }
+                    return Poll::Ready(Some(Ok(take_buf(this.buf, this.flush_timer))));
+                }
+                Poll::Pending => {
+                    if let Some(timer) = this.flush_timer.as_mut() {
+                        if timer.as_mut().poll(cx).is_ready() {
+                            return Poll::Ready(Some(Ok(take_buf(this.buf, this.flush_timer))));
+                        }
+                    }
+                    return Poll::Pending;
+                }
+            }
+        }
+    }
+}
+
+fn take_buf(buf: &mut Vec<u8>, flush_timer: &mut Option<Pin<Box<Sleep>>>) -> Bytes {
+    *flush_timer = None;
+    Bytes::from(std::mem::take(buf))
+}
+
+/// Stream adapter counting the bytes and chunks passing through it.
+///
+/// The counters are shared so they can be read from another task, for example to report
+/// progress of an image pull.
+#[pin_project]
+#[derive(Debug)]
+pub struct StreamStats<S> {
+    #[pin]
+    stream: S,
+    bytes: Arc<AtomicU64>,
+    chunks: Arc<AtomicU64>,
+}
+
+impl<S> StreamStats<S> {
+    pub fn new(stream: S) -> Self {
+        Self {
+            stream,
+            bytes: Default::default(),
+            chunks: Default::default(),
+        }
+    }
+
+    /// Returns the shared counters of bytes and chunks, in that order.
+    pub fn counters(&self) -> (Arc<AtomicU64>, Arc<AtomicU64>) {
+        (self.bytes.clone(), self.chunks.clone())
+    }
+
+    /// Number of bytes received so far.
+    pub fn bytes(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    /// Number of chunks received so far.
+    pub fn chunks(&self) -> u64 {
+        self.chunks.load(Ordering::Relaxed)
+    }
+
+    /// Returns the underlying stream.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+impl<S, E> Stream for StreamStats<S>
+where
+    S: Stream<Item = Result<Bytes, E>>,
+{
+    type Item = Result<Bytes, E>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let item = this.stream.poll_next(cx);
+        if let Poll::Ready(Some(Ok(chunk))) = &item {
+            this.bytes.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+            this.chunks.fetch_add(1, Ordering::Relaxed);
+        }
+        item
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{rechunk, StreamStats};
+    use futures_util::{stream, StreamExt, TryStreamExt};
+    use hyper::body::Bytes;
+    use std::{convert::Infallible, sync::atomic::Ordering, time::Duration};
+
+    fn chunks(sizes: &[usize]) -> impl futures_util::Stream<Item = Result<Bytes, Infallible>> {
+        let mut n = 0u8;
+        let chunks = sizes
+            .iter()
+            .map(|size| {
+                let chunk = (0..*size)
+                    .map(|_| {
+                        n = n.wrapping_add(1);
+                        n
+                    })
+                    .collect::<Vec<_>>();
+                Ok(Bytes::from(chunk))
+            })
+            .collect::<Vec<_>>();
+        stream::iter(chunks)
+    }
+
+    async fn sizes<S: futures_util::Stream<Item = Result<Bytes, Infallible>>>(
+        stream: S,
+    ) -> (Vec<usize>, Vec<u8>) {
+        let out = stream.try_collect::<Vec<_>>().await.unwrap();
+        (out.iter().map(|c| c.len()).collect(), out.concat())
+    }
+
+    #[tokio::test]
+    async fn splits_large_chunks() {
+        let (got, data) = sizes(rechunk(chunks(&[10, 3, 0, 8]), 4)).await;
+        assert_eq!(got, [4, 4, 2, 3, 4, 4]);
+        assert_eq!(data, (1..=21).collect::<Vec<u8>>());
+    }
+
+    #[tokio::test]
+    async fn coalesces_small_chunks() {
+        let stream = rechunk(chunks(&[1, 2, 1, 9, 1, 1]), 5).coalesce(3, Duration::from_secs(60));
+        let (got, data) = sizes(stream).await;
+        assert_eq!(got, [3, 5, 5, 2]);
+        assert_eq!(data, (1..=15).collect::<Vec<u8>>());
+    }
+
+    #[tokio::test]
+    async fn flushes_coalesced_chunks_after_interval() {
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel::<Result<Bytes, Infallible>>();
+        let rx = stream::unfold(rx, |mut rx| async { rx.recv().await.map(|c| (c, rx)) });
+        let mut stream = rechunk(Box::pin(rx), 100).coalesce(50, Duration::from_millis(20));
+
+        tx.send(Ok(Bytes::from_static(b"abc"))).unwrap();
+        let chunk = stream.next().await.unwrap().unwrap();
+        assert_eq!(chunk, "abc");
+
+        tx.send(Ok(Bytes::from_static(b"de"))).unwrap();
+        drop(tx);
+        let chunk = stream.next().await.unwrap().unwrap();
+        assert_eq!(chunk, "de");
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn counts_bytes_and_chunks() {
+        let stream = StreamStats::new(chunks(&[10, 3, 0, 8]));
+        let (bytes, count) = stream.counters();
+
+        let mut stream = rechunk(stream, 4);
+        stream.next().await.unwrap().unwrap();
+        assert_eq!(bytes.load(Ordering::Relaxed), 10);
+        assert_eq!(count.load(Ordering::Relaxed), 1);
+
+        stream.try_collect::<Vec<_>>().await.unwrap();
+        assert_eq!(bytes.load(Ordering::Relaxed), 21);
+        assert_eq!(count.load(Ordering::Relaxed), 4);
+    }
+}
@@ -1,39 +1,149 @@
 use crate::conn::{
-    self, build_request, get_response_string, stream_json_response, stream_response, Compat,
-    Headers, Payload, Transport,
+    self, json,
+    lowlevel::{
+        build_request, get_response_string, send_and_validate, stream_json_response,
+        stream_response,
+    },
+    upgrade::{BodySender, UpgradedConnection, RAW_STREAM_CONTENT_TYPES},
+    Headers, JsonStream, Payload, RangedStream, ReconnectPolicy, Transport, UpgradedStream,
 };
 use futures_util::{
-    io::{AsyncRead, AsyncWrite},
-    stream::Stream,
+    stream::{BoxStream, Stream, StreamExt},
     TryFutureExt, TryStreamExt,
 };
-use hyper::{body::Bytes, header, Body, Method, Request, Response, StatusCode};
+use hyper::{body::Bytes, header, Body, HeaderMap, Method, Request, Response, StatusCode};
 use log::trace;
 use serde::de::DeserializeOwned;
+use std::fmt;
 use std::future::Future;
 use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
 
-#[derive(Debug, Clone)]
 pub struct RequestClient<E> {
     transport: Transport,
-    validate_fn: Box<ValidateResponseFn<E>>,
+    validate_fn: Validator<E>,
+    default_headers: Option<Headers>,
+    #[cfg(feature = "opentelemetry")]
+    tracer: Option<Arc<opentelemetry::global::BoxedTracer>>,
     _error_type: std::marker::PhantomData<E>,
 }
 
-pub type ValidateResponseFn<E> =
-    fn(Response<Body>) -> Pin<Box<dyn Future<Output = Result<Response<Body>, E>> + Send + Sync>>;
+impl<E> Clone for RequestClient<E> {
+    fn clone(&self) -> Self {
+        Self {
+            transport: self.transport.clone(),
+            validate_fn: self.validate_fn.clone(),
+            default_headers: self.default_headers.clone(),
+            #[cfg(feature = "opentelemetry")]
+            tracer: self.tracer.clone(),
+            _error_type: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<E> fmt::Debug for RequestClient<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("RequestClient");
+        debug
+            .field("transport", &self.transport)
+            .field("validate_fn", &"<validator>")
+            .field("default_headers", &self.default_headers);
+        #[cfg(feature = "opentelemetry")]
+        debug.field("tracer", &self.tracer.as_ref().map(|_| "<tracer>"));
+        debug.finish()
+    }
+}
+
+/// Future returned from a response validator.
+pub type ValidateResponseFuture<E> =
+    Pin<Box<dyn Future<Output = Result<Response<Body>, E>> + Send + Sync>>;
+
+pub type ValidateResponseFn<E> = fn(Response<Body>) -> ValidateResponseFuture<E>;
+
+/// A validator that can capture state, see [`RequestClient::with_validator`].
+pub type ResponseValidator<E> = dyn Fn(Response<Body>) -> ValidateResponseFuture<E> + Send + Sync;
+
+/// A function pointer is kept as is, as putting it behind `dyn Fn` would require `E: 'static`.
+enum Validator<E> {
+    Fn(ValidateResponseFn<E>),
+    Closure(Arc<ResponseValidator<E>>),
+}
+
+impl<E> Validator<E> {
+    fn validate(&self, response: Response<Body>) -> ValidateResponseFuture<E> {
+        match self {
+            Self::Fn(validate_fn) => validate_fn(response),
+            Self::Closure(validator) => validator(response),
+        }
+    }
+}
+
+impl<E> Clone for Validator<E> {
+    fn clone(&self) -> Self {
+        match self {
+            Self::Fn(validate_fn) => Self::Fn(*validate_fn),
+            Self::Closure(validator) => Self::Closure(Arc::clone(validator)),
+        }
+    }
+}
 
 impl<E: From<conn::Error> + From<serde_json::Error>> RequestClient<E> {
     /// Creates a new RequestClient with a specified transport and a function to validate
     /// each response.
+    #[allow(clippy::boxed_local)]
     pub fn new(transport: Transport, validate_fn: Box<ValidateResponseFn<E>>) -> Self {
+        Self::with_validator_impl(transport, Validator::Fn(*validate_fn))
+    }
+
+    /// Creates a new RequestClient with a specified transport and a closure used to validate
+    /// each response. Unlike [`RequestClient::new`] the closure may capture state, for example
+    /// a table mapping status codes to errors.
+    pub fn with_validator<F>(transport: Transport, validator: F) -> Self
+    where
+        F: Fn(Response<Body>) -> ValidateResponseFuture<E> + Send + Sync + 'static,
+    {
+        Self::with_validator_impl(transport, Validator::Closure(Arc::new(validator)))
+    }
+
+    fn with_validator_impl(transport: Transport, validate_fn: Validator<E>) -> Self {
         Self {
             transport,
             validate_fn,
+            default_headers: None,
+            #[cfg(feature = "opentelemetry")]
+            tracer: None,
             _error_type: std::marker::PhantomData,
         }
     }
 
+    #[cfg(feature = "opentelemetry")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "opentelemetry")))]
+    /// Creates a client span with `tracer` for every request. The span context is propagated to
+    /// the daemon in the request headers using the global text map propagator and the span
+    /// records the method, endpoint and response status.
+    pub fn with_tracer<T, S>(mut self, tracer: T) -> Self
+    where
+        T: opentelemetry::trace::Tracer<Span = S> + Send + Sync + 'static,
+        S: opentelemetry::trace::Span + Send + Sync + 'static,
+    {
+        self.tracer = Some(Arc::new(opentelemetry::global::BoxedTracer::new(Box::new(
+            tracer,
+        ))));
+        self
+    }
+
+    /// Sends `headers` with every request made by this client. Headers passed to a single
+    /// request take precedence, all values of a default header are replaced by them.
+    ///
+    /// Defaults also take precedence over headers set by the client itself, like the
+    /// `Content-Type` of the payload, except for the `Connection` and `Upgrade` headers of
+    /// upgraded requests.
+    pub fn with_default_headers(mut self, headers: Headers) -> Self {
+        self.default_headers = Some(headers);
+        self
+    }
+
     fn make_request<B>(
         &self,
         method: http::Method,
@@ -45,12 +155,52 @@ impl<E: From<conn::Error> + From<serde_json::Error>> RequestClient<E> {
         B: Into<Body>,
     {
         let uri = self.transport.make_uri(endpoint)?;
+        let headers = match (&self.default_headers, headers) {
+            (Some(defaults), Some(headers)) => {
+                let mut defaults = defaults.clone();
+                defaults.override_with(headers);
+                Some(defaults)
+            }
+            (defaults, headers) => headers.or_else(|| defaults.clone()),
+        };
         build_request(method, uri, body, headers)
     }
 
     async fn send_request(&self, request: Request<Body>) -> Result<Response<Body>, E> {
-        let response = self.transport.request(request).await.map_err(E::from)?;
-        (self.validate_fn)(response).await
+        #[cfg(feature = "opentelemetry")]
+        if let Some(tracer) = &self.tracer {
+            return self.send_traced_request(tracer, request).await;
+        }
+
+        send_and_validate(&self.transport, request, &|response| {
+            self.validate_fn.validate(response)
+        })
+        .await
+    }
+
+    #[cfg(feature = "opentelemetry")]
+    async fn send_traced_request(
+        &self,
+        tracer: &opentelemetry::global::BoxedTracer,
+        mut request: Request<Body>,
+    ) -> Result<Response<Body>, E> {
+        let span = conn::otel::RequestSpan::start(tracer, &mut request);
+        let result = match self.transport.request(request).await {
+            Ok(response) => {
+                span.record_status(response.status());
+                let result = self.validate_fn.validate(response).await;
+                if result.is_err() {
+                    span.record_error(&"response validation failed");
+                }
+                result
+            }
+            Err(e) => {
+                span.record_error(&e);
+                Err(E::from(e))
+            }
+        };
+        span.end();
+        result
     }
 
     //####################################################################################################
@@ -59,26 +209,76 @@ impl<E: From<conn::Error> + From<serde_json::Error>> RequestClient<E> {
 
     /// Make a GET request to the `endpoint` and return the response.
     pub async fn get(&self, endpoint: impl AsRef<str>) -> Result<Response<Body>, E> {
-        let req = self.make_request(
-            Method::GET,
-            endpoint.as_ref(),
-            Payload::empty(),
-            Headers::none(),
-        );
+        self.get_with_headers(endpoint, Headers::none()).await
+    }
+
+    /// Make a GET request to the `endpoint` with additional `headers` and return the response.
+    pub async fn get_with_headers(
+        &self,
+        endpoint: impl AsRef<str>,
+        headers: Option<Headers>,
+    ) -> Result<Response<Body>, E> {
+        let req = self.make_request(Method::GET, endpoint.as_ref(), Payload::empty(), headers);
         self.send_request(req?).await
     }
 
+    /// Make a GET request to the `endpoint` and return the validated response with the body left
+    /// untouched, so that the caller can decide how to consume it after inspecting the headers.
+    /// Trailers can be retrieved with [`read_trailers`](conn::read_trailers).
+    pub async fn get_raw(&self, endpoint: impl AsRef<str>) -> Result<Response<Body>, E> {
+        self.get(endpoint).await
+    }
+
     /// Make a GET request to the `endpoint` and return the response as a string.
     pub async fn get_string(&self, endpoint: impl AsRef<str>) -> Result<String, E> {
-        let response = self.get(endpoint).await?;
+        self.get_string_with_headers(endpoint, Headers::none())
+            .await
+    }
+
+    /// Make a GET request to the `endpoint` with additional `headers` and return the response
+    /// as a string.
+    pub async fn get_string_with_headers(
+        &self,
+        endpoint: impl AsRef<str>,
+        headers: Option<Headers>,
+    ) -> Result<String, E> {
+        let response = self.get_with_headers(endpoint, headers).await?;
         get_response_string(response).await.map_err(E::from)
     }
 
     /// Make a GET request to the `endpoint` and return the response as a JSON deserialized object.
     pub async fn get_json<T: DeserializeOwned>(&self, endpoint: impl AsRef<str>) -> Result<T, E> {
-        let raw_string = self.get_string(endpoint).await?;
+        self.get_json_with_headers(endpoint, Headers::none()).await
+    }
+
+    /// Make a GET request to the `endpoint` with additional `headers` and return the response
+    /// as a JSON deserialized object.
+    pub async fn get_json_with_headers<T: DeserializeOwned>(
+        &self,
+        endpoint: impl AsRef<str>,
+        headers: Option<Headers>,
+    ) -> Result<T, E> {
+        let endpoint = endpoint.as_ref();
+        let raw_string = self.get_string_with_headers(endpoint, headers).await?;
         trace!("{raw_string}");
-        serde_json::from_str::<T>(&raw_string).map_err(E::from)
+        json::from_slice(endpoint, raw_string.as_bytes()).map_err(E::from)
+    }
+
+    /// Make a GET request to the `endpoint` and return the response as a JSON deserialized
+    /// object, or `T::default()` if the response is `204 No Content`. Use this with endpoints
+    /// that only return a body when there is something to report.
+    pub async fn get_json_or_empty<T>(&self, endpoint: impl AsRef<str>) -> Result<T, E>
+    where
+        T: DeserializeOwned + Default,
+    {
+        let endpoint = endpoint.as_ref();
+        let response = self.get(endpoint).await?;
+        if response.status() == StatusCode::NO_CONTENT {
+            return Ok(T::default());
+        }
+        let raw_string = get_response_string(response).await.map_err(E::from)?;
+        trace!("{raw_string}");
+        json::from_slice(endpoint, raw_string.as_bytes()).map_err(E::from)
     }
 
     async fn get_stream_impl(
@@ -97,26 +297,164 @@ impl<E: From<conn::Error> + From<serde_json::Error>> RequestClient<E> {
         self.get_stream_impl(endpoint).try_flatten_stream()
     }
 
+    /// Make a GET request to the `endpoint` requesting only the specified `range` of bytes and
+    /// return a stream of byte chunks together with information about the received range.
+    ///
+    /// The range is a tuple of the first byte and an optional last byte (inclusive). If `strict`
+    /// is true and the server ignores the range by sending the whole resource
+    /// [`Error::RangeIgnored`](conn::Error::RangeIgnored) is returned, otherwise the returned
+    /// stream starts at offset `0`.
+    pub async fn get_stream_ranged(
+        &self,
+        endpoint: impl AsRef<str>,
+        range: Option<(u64, Option<u64>)>,
+        strict: bool,
+    ) -> Result<RangedStream<impl Stream<Item = Result<Bytes, E>>>, E> {
+        let headers = range.map(|(start, end)| {
+            Headers::single(
+                header::RANGE.as_str(),
+                conn::range::range_header(start, end),
+            )
+        });
+        let req = self.make_request(Method::GET, endpoint.as_ref(), Payload::empty(), headers);
+        let response = self.send_request(req?).await?;
+
+        if strict && range.is_some() && response.status() != StatusCode::PARTIAL_CONTENT {
+            return Err(E::from(conn::Error::RangeIgnored));
+        }
+
+        Ok(RangedStream::new(response, |response| {
+            stream_response(response).map_err(E::from)
+        }))
+    }
+
     /// Make a GET request to the `endpoint` and return a stream of JSON chunk results.
     pub fn get_json_stream<'client, T>(
         &'client self,
         endpoint: impl AsRef<str> + 'client,
-    ) -> impl Stream<Item = Result<T, E>> + 'client
+    ) -> JsonStream<impl Stream<Item = Result<Bytes, E>> + 'client, T, E>
     where
         T: DeserializeOwned,
     {
-        self.get_stream(endpoint)
-            .and_then(|chunk| async move {
-                let stream = futures_util::stream::iter(
-                    serde_json::Deserializer::from_slice(&chunk)
-                        .into_iter()
-                        .collect::<Vec<_>>(),
-                )
-                .map_err(E::from);
-
-                Ok(stream)
-            })
-            .try_flatten()
+        let ep = endpoint.as_ref().to_string();
+        JsonStream::new(ep, self.get_stream(endpoint))
+    }
+
+    async fn get_resumable_response(&self, endpoint: &str) -> Result<Response<Body>, (E, bool)> {
+        let req = self
+            .make_request(Method::GET, endpoint, Payload::empty(), Headers::none())
+            .map_err(|e| (E::from(e), false))?;
+        let response = self.transport.request(req).await.map_err(|e| {
+            let transient = e.is_transient();
+            (E::from(e), transient)
+        })?;
+        let transient = response.status().is_server_error();
+        self.validate_fn
+            .validate(response)
+            .await
+            .map_err(|e| (e, transient))
+    }
+
+    /// Make a GET request to the endpoint returned by `endpoint_fn` and return a stream of JSON
+    /// chunk results that reconnects according to `policy` whenever the connection ends or fails.
+    ///
+    /// Every received item is passed to `checkpoint_fn` and the last returned checkpoint is
+    /// handed to `endpoint_fn` when reconnecting, so that the new request can resume right after
+    /// the last received item (for example by passing `since=` to the events endpoint).
+    ///
+    /// Only transient errors (see [`conn::Error::is_transient`]) and server errors (5xx) cause a
+    /// reconnect, any other error when connecting is permanent and ends the stream. Errors
+    /// that occur while receiving items, like a failure to deserialize one of them, are
+    /// yielded without closing the connection.
+    ///
+    /// The returned stream is `Send` so it can be spawned on a runtime.
+    pub fn get_json_stream_resumable<'client, T, C, EF, CF>(
+        &'client self,
+        endpoint_fn: EF,
+        checkpoint_fn: CF,
+        policy: ReconnectPolicy,
+    ) -> impl Stream<Item = Result<T, E>> + Send + 'client
+    where
+        T: DeserializeOwned + Send + 'client,
+        C: Clone + Send + 'client,
+        EF: Fn(Option<C>) -> String + Send + 'client,
+        CF: Fn(&T) -> Option<C> + Send + 'client,
+        E: Send + Sync + 'client,
+    {
+        struct State<'client, E, T, C, EF, CF> {
+            client: &'client RequestClient<E>,
+            endpoint_fn: EF,
+            checkpoint_fn: CF,
+            policy: ReconnectPolicy,
+            checkpoint: Option<C>,
+            stream: Option<BoxStream<'client, conn::Result<T>>>,
+            attempt: usize,
+        }
+
+        let state = State {
+            client: self,
+            endpoint_fn,
+            checkpoint_fn,
+            policy,
+            checkpoint: None,
+            stream: None,
+            attempt: 0,
+        };
+
+        futures_util::stream::unfold(Some(state), |state| async move {
+            let mut state = state?;
+            loop {
+                if let Some(stream) = state.stream.as_mut() {
+                    match stream.next().await {
+                        Some(Ok(item)) => {
+                            if let Some(checkpoint) = (state.checkpoint_fn)(&item) {
+                                state.checkpoint = Some(checkpoint);
+                            }
+                            state.attempt = 0;
+                            return Some((Ok(item), Some(state)));
+                        }
+                        Some(Err(e)) if e.is_transient() => {
+                            state.stream = None;
+                            state.attempt += 1;
+                            if !state.policy.allows(state.attempt) {
+                                return Some((Err(E::from(e)), None));
+                            }
+                            trace!("resumable stream failed, reconnecting");
+                        }
+                        Some(Err(e)) => return Some((Err(E::from(e)), Some(state))),
+                        None => {
+                            state.stream = None;
+                            state.attempt += 1;
+                            if !state.policy.allows(state.attempt) {
+                                return None;
+                            }
+                            trace!("resumable stream ended, reconnecting");
+                        }
+                    }
+                }
+
+                if state.attempt > 0 {
+                    tokio::time::sleep(state.policy.backoff(state.attempt)).await;
+                }
+
+                let endpoint = (state.endpoint_fn)(state.checkpoint.clone());
+                match state.client.get_resumable_response(&endpoint).await {
+                    Ok(response) => {
+                        state.stream = Some(Box::pin(JsonStream::new(
+                            endpoint,
+                            stream_response(response),
+                        )));
+                    }
+                    Err((e, transient)) => {
+                        state.attempt += 1;
+                        if !transient || !state.policy.allows(state.attempt) {
+                            return Some((Err(e), None));
+                        }
+                        trace!("failed to reconnect resumable stream, retrying");
+                    }
+                }
+            }
+        })
     }
 
     //####################################################################################################
@@ -137,6 +475,20 @@ impl<E: From<conn::Error> + From<serde_json::Error>> RequestClient<E> {
         self.send_request(req?).await
     }
 
+    /// Make a POST request to the `endpoint` and return the validated response with the body
+    /// left untouched. See [`get_raw`](RequestClient::get_raw).
+    pub async fn post_raw<B>(
+        &self,
+        endpoint: impl AsRef<str>,
+        body: Payload<B>,
+        headers: Option<Headers>,
+    ) -> Result<Response<Body>, E>
+    where
+        B: Into<Body>,
+    {
+        self.post(endpoint, body, headers).await
+    }
+
     /// Make a POST request to the `endpoint` and return the response as a string.
     pub async fn post_string<B>(
         &self,
@@ -163,9 +515,10 @@ impl<E: From<conn::Error> + From<serde_json::Error>> RequestClient<E> {
         T: DeserializeOwned,
         B: Into<Body>,
     {
+        let endpoint = endpoint.as_ref();
         let raw_string = self.post_string(endpoint, body, headers).await?;
         trace!("{raw_string}");
-        serde_json::from_str::<T>(&raw_string).map_err(E::from)
+        json::from_slice(endpoint, raw_string.as_bytes()).map_err(E::from)
     }
 
     async fn post_stream_impl<B>(
@@ -233,37 +586,47 @@ impl<E: From<conn::Error> + From<serde_json::Error>> RequestClient<E> {
         endpoint: impl AsRef<str> + 'client,
         body: Payload<B>,
         headers: Option<Headers>,
-    ) -> impl Stream<Item = Result<T, E>> + 'client
+    ) -> JsonStream<impl Stream<Item = Result<Bytes, E>> + 'client, T, E>
     where
         B: Into<Body> + 'client,
         T: DeserializeOwned,
     {
-        self.post_json_stream(endpoint, body, headers)
-            .and_then(|chunk| async move {
-                trace!("got chunk {:?}", chunk);
-                let stream = futures_util::stream::iter(
-                    serde_json::Deserializer::from_slice(&chunk)
-                        .into_iter()
-                        .collect::<Vec<_>>(),
-                )
-                .map_err(E::from);
-
-                Ok(stream)
-            })
-            .try_flatten()
+        let ep = endpoint.as_ref().to_string();
+        JsonStream::new(
+            ep,
+            self.post_json_stream(endpoint, body, headers)
+                .inspect_ok(|chunk| trace!("got chunk {:?}", chunk)),
+        )
     }
 
     pub async fn post_upgrade_stream<B>(
         self,
         endpoint: impl AsRef<str>,
         body: Payload<B>,
-    ) -> Result<impl AsyncRead + AsyncWrite, E>
+    ) -> Result<UpgradedStream, E>
     where
         B: Into<Body>,
     {
-        self.stream_upgrade(Method::POST, endpoint, body)
+        self.stream_upgrade(Method::POST, endpoint, body).await
+    }
+
+    /// Make a POST request to the `endpoint` of a daemon that hands off the connection as a raw
+    /// stream right after any `2xx` response, without `101 Switching Protocols`.
+    ///
+    /// The request body is streamed, `body` is sent chunk by chunk from a spawned task and
+    /// everything written to the returned stream follows it, writes wait until `body` was sent. Closing the write half of the stream ends the request body. If the
+    /// daemon does switch protocols the stream is the upgraded connection instead.
+    pub async fn post_raw_stream<B>(
+        &self,
+        endpoint: impl AsRef<str>,
+        body: Payload<B>,
+    ) -> Result<UpgradedStream, E>
+    where
+        B: Into<Body>,
+    {
+        self.stream_upgrade_tokio(Method::POST, endpoint.as_ref(), body, true)
             .await
-            .map_err(E::from)
+            .map(UpgradedStream::new)
     }
 
     //####################################################################################################
@@ -296,24 +659,53 @@ impl<E: From<conn::Error> + From<serde_json::Error>> RequestClient<E> {
         get_response_string(response).await.map_err(E::from)
     }
 
+    /// Make a PUT request to the `endpoint` and return the response as a JSON
+    /// deserialized value.
+    pub async fn put_json<B, T>(&self, endpoint: impl AsRef<str>, body: Payload<B>) -> Result<T, E>
+    where
+        T: DeserializeOwned,
+        B: Into<Body>,
+    {
+        let endpoint = endpoint.as_ref();
+        let raw_string = self.put_string(endpoint, body).await?;
+        trace!("{raw_string}");
+        json::from_slice(endpoint, raw_string.as_bytes()).map_err(E::from)
+    }
+
     //####################################################################################################
     // DELETE
     //####################################################################################################
 
     /// Make a DELETE request to the `endpoint` and return the response.
     pub async fn delete(&self, endpoint: impl AsRef<str>) -> Result<Response<Body>, E> {
-        let req = self.make_request(
-            Method::DELETE,
-            endpoint.as_ref(),
-            Payload::empty(),
-            Headers::none(),
-        );
+        self.delete_with_headers(endpoint, Headers::none()).await
+    }
+
+    /// Make a DELETE request to the `endpoint` with additional `headers` and return the
+    /// response.
+    pub async fn delete_with_headers(
+        &self,
+        endpoint: impl AsRef<str>,
+        headers: Option<Headers>,
+    ) -> Result<Response<Body>, E> {
+        let req = self.make_request(Method::DELETE, endpoint.as_ref(), Payload::empty(), headers);
         self.send_request(req?).await
     }
 
     /// Make a DELETE request to the `endpoint` and return the response as a string.
     pub async fn delete_string(&self, endpoint: impl AsRef<str>) -> Result<String, E> {
-        let response = self.delete(endpoint).await?;
+        self.delete_string_with_headers(endpoint, Headers::none())
+            .await
+    }
+
+    /// Make a DELETE request to the `endpoint` with additional `headers` and return the
+    /// response as a string.
+    pub async fn delete_string_with_headers(
+        &self,
+        endpoint: impl AsRef<str>,
+        headers: Option<Headers>,
+    ) -> Result<String, E> {
+        let response = self.delete_with_headers(endpoint, headers).await?;
         get_response_string(response).await.map_err(E::from)
     }
 
@@ -323,9 +715,21 @@ impl<E: From<conn::Error> + From<serde_json::Error>> RequestClient<E> {
         &self,
         endpoint: impl AsRef<str>,
     ) -> Result<T, E> {
-        let raw_string = self.delete_string(endpoint).await?;
+        self.delete_json_with_headers(endpoint, Headers::none())
+            .await
+    }
+
+    /// Make a DELETE request to the `endpoint` with additional `headers` and return the
+    /// response as a JSON deserialized object.
+    pub async fn delete_json_with_headers<T: DeserializeOwned>(
+        &self,
+        endpoint: impl AsRef<str>,
+        headers: Option<Headers>,
+    ) -> Result<T, E> {
+        let endpoint = endpoint.as_ref();
+        let raw_string = self.delete_string_with_headers(endpoint, headers).await?;
         trace!("{raw_string}");
-        serde_json::from_str::<T>(&raw_string).map_err(E::from)
+        json::from_slice(endpoint, raw_string.as_bytes()).map_err(E::from)
     }
 
     //####################################################################################################
@@ -343,6 +747,28 @@ impl<E: From<conn::Error> + From<serde_json::Error>> RequestClient<E> {
         self.send_request(req?).await
     }
 
+    /// Make a HEAD request to the `endpoint` and return the headers of the response, for example
+    /// to check the `Content-Length` of a resource before downloading it. Use
+    /// [`head`](RequestClient::head) if the status code is needed as well.
+    pub async fn head_headers(&self, endpoint: impl AsRef<str>) -> Result<HeaderMap, E> {
+        self.head(endpoint)
+            .await
+            .map(|response| response.into_parts().0.headers)
+    }
+
+    //####################################################################################################
+    // HEALTHCHECK
+    //####################################################################################################
+
+    /// Checks whether the daemon is reachable by sending a `HEAD` request to the `endpoint`
+    /// and returns the round-trip latency. See [`Transport::ping_endpoint`] for details.
+    pub async fn healthcheck(&self, endpoint: impl AsRef<str>) -> Result<Duration, E> {
+        self.transport
+            .ping_endpoint(endpoint.as_ref())
+            .await
+            .map_err(E::from)
+    }
+
     //####################################################################################################
     // STREAM
     //####################################################################################################
@@ -352,38 +778,869 @@ impl<E: From<conn::Error> + From<serde_json::Error>> RequestClient<E> {
         method: Method,
         endpoint: impl AsRef<str>,
         body: Payload<B>,
-    ) -> Result<impl AsyncRead + AsyncWrite, E>
+    ) -> Result<UpgradedStream, E>
     where
         B: Into<Body>,
     {
-        self.stream_upgrade_tokio(method, endpoint.as_ref(), body)
+        self.stream_upgrade_tokio(method, endpoint.as_ref(), body, false)
             .await
-            .map(Compat::new)
+            .map(UpgradedStream::new)
     }
 
     /// Makes an HTTP request, upgrading the connection to a TCP
     /// stream on success.
+    ///
+    /// Some daemons answer with `200 OK` and a raw stream content type instead of
+    /// `101 Switching Protocols`. In that case the returned connection reads from the
+    /// response body and writes to the request body, which is sent through a channel.
+    ///
+    /// If `any_success` is true every `2xx` response is accepted regardless of its content type.
+    ///
+    /// HTTP/2 has no connection upgrades so this fails with
+    /// [`Error::UpgradeNotSupported`](conn::Error::UpgradeNotSupported) on an HTTP/2 transport.
     async fn stream_upgrade_tokio<B>(
         &self,
         method: Method,
         endpoint: &str,
         body: Payload<B>,
-    ) -> Result<hyper::upgrade::Upgraded, E>
+        any_success: bool,
+    ) -> Result<UpgradedConnection, E>
     where
         B: Into<Body>,
     {
+        if self.transport.is_http2() {
+            return Err(E::from(conn::Error::UpgradeNotSupported));
+        }
+
         let mut headers = Headers::default();
         headers.add(header::CONNECTION.as_str(), "Upgrade");
         headers.add(header::UPGRADE.as_str(), "tcp");
 
-        let req = self.make_request(method, endpoint, body, Some(headers));
+        // the request body is sent through a channel, so that the returned connection can
+        // write to it when the daemon doesn't switch protocols
+        let mut req = self.make_request(method, endpoint, body, Some(headers))?;
+        let (sender, channel) = Body::channel();
+        let initial = std::mem::replace(req.body_mut(), channel);
+        let sender = BodySender::forward(initial, sender);
 
-        let response = self.send_request(req?).await?;
+        let response = self.send_request(req).await?;
         match response.status() {
-            StatusCode::SWITCHING_PROTOCOLS => Ok(hyper::upgrade::on(response)
-                .await
-                .map_err(conn::Error::from)?),
+            StatusCode::SWITCHING_PROTOCOLS => {
+                // hyper only hands over the connection once the request body is complete
+                sender.await;
+                Ok(UpgradedConnection::Upgraded(
+                    hyper::upgrade::on(response)
+                        .await
+                        .map_err(conn::Error::from)?,
+                ))
+            }
+            StatusCode::OK if is_raw_stream(&response) => {
+                Ok(UpgradedConnection::from_body(response.into_body(), sender))
+            }
+            status if any_success && status.is_success() => {
+                Ok(UpgradedConnection::from_body(response.into_body(), sender))
+            }
             _ => Err(E::from(conn::Error::ConnectionNotUpgraded)),
         }
     }
 }
+
+fn is_raw_stream(response: &Response<Body>) -> bool {
+    response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|ct| ct.to_str().ok())
+        .map(|ct| {
+            RAW_STREAM_CONTENT_TYPES
+                .iter()
+                .any(|raw| ct.starts_with(raw))
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::conn::{self, stub, Error, Headers, Payload, ReconnectPolicy, RequestClient};
+    use futures_util::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        StreamExt, TryStreamExt,
+    };
+    use hyper::{body::Bytes, header, Body, HeaderMap, Response, StatusCode};
+    use serde::Deserialize;
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        time::Duration,
+    };
+
+    #[derive(Debug, Deserialize)]
+    struct Event {
+        time: usize,
+    }
+
+    fn since(endpoint: &str) -> usize {
+        endpoint
+            .split("since=")
+            .nth(1)
+            .and_then(|since| since.parse().ok())
+            .unwrap_or_default()
+    }
+
+    #[tokio::test]
+    async fn resumable_stream_delivers_every_event_once() {
+        const EVENTS: usize = 10;
+        const PER_CONNECTION: usize = 3;
+
+        let addr = stub::serve(|req| async move {
+            let since = since(&req.uri().to_string());
+            let body = (since + 1..=EVENTS)
+                .take(PER_CONNECTION)
+                .map(|time| format!("{{\"time\":{time}}}\r\n"))
+                .collect::<String>();
+            Response::new(Body::from(body))
+        });
+        let client = stub::client(addr);
+
+        let events = client
+            .get_json_stream_resumable(
+                |checkpoint: Option<usize>| match checkpoint {
+                    Some(since) => format!("events?since={since}"),
+                    None => "events".into(),
+                },
+                |event: &Event| Some(event.time),
+                ReconnectPolicy::new().initial_backoff(Duration::from_millis(1)),
+            )
+            .take(EVENTS)
+            .try_collect::<Vec<Event>>()
+            .await
+            .unwrap();
+
+        let times = events.into_iter().map(|e| e.time).collect::<Vec<_>>();
+        assert_eq!(times, (1..=EVENTS).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn resumable_stream_does_not_reconnect_on_client_error() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        let addr = stub::serve(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            async {
+                Response::builder()
+                    .status(StatusCode::NOT_FOUND)
+                    .body(Body::from("no such endpoint"))
+                    .unwrap()
+            }
+        });
+        let client = stub::client(addr);
+
+        let results = client
+            .get_json_stream_resumable(
+                |_: Option<usize>| "events".into(),
+                |event: &Event| Some(event.time),
+                ReconnectPolicy::new().initial_backoff(Duration::from_millis(1)),
+            )
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(results.len(), 1);
+        assert!(matches!(
+            results[0],
+            Err(Error::Fault {
+                code: StatusCode::NOT_FOUND,
+                ..
+            })
+        ));
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn resumable_stream_yields_deserialize_errors() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        let addr = stub::serve(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            async {
+                let chunks = ["{\"time\":1}\r\n", "not json\r\n", "{\"time\":2}\r\n"];
+                Response::new(Body::wrap_stream(futures_util::stream::iter(
+                    chunks.map(Ok::<_, std::io::Error>),
+                )))
+            }
+        });
+        // the stream borrows the client, leak it so that the stream can be spawned
+        let client: &'static RequestClient<Error> = Box::leak(Box::new(stub::client(addr)));
+
+        let stream = client.get_json_stream_resumable(
+            |_: Option<usize>| "events".into(),
+            |event: &Event| Some(event.time),
+            ReconnectPolicy::new().initial_backoff(Duration::from_millis(1)),
+        );
+        let results = tokio::spawn(stream.take(3).collect::<Vec<_>>())
+            .await
+            .unwrap();
+
+        assert!(matches!(results[0], Ok(Event { time: 1 })));
+        assert!(matches!(results[1], Err(Error::Deserialize { .. })));
+        assert!(matches!(results[2], Ok(Event { time: 2 })));
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn resumable_stream_gives_up_after_max_retries() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        let addr = stub::serve(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            async {
+                Response::builder()
+                    .status(StatusCode::SERVICE_UNAVAILABLE)
+                    .body(Body::empty())
+                    .unwrap()
+            }
+        });
+        let client = stub::client(addr);
+
+        let results = client
+            .get_json_stream_resumable(
+                |_: Option<usize>| "events".into(),
+                |event: &Event| Some(event.time),
+                ReconnectPolicy::new()
+                    .max_retries(2)
+                    .initial_backoff(Duration::from_millis(1)),
+            )
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(results.len(), 1);
+        assert!(results[0].is_err());
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+    }
+
+    async fn concat(stream: impl futures_util::Stream<Item = Result<Bytes, Error>>) -> Vec<u8> {
+        stream.map_ok(|b| b.to_vec()).try_concat().await.unwrap()
+    }
+
+    fn ranged_server(honor_range: bool) -> std::net::SocketAddr {
+        const DATA: &str = "0123456789";
+        stub::serve(move |req| async move {
+            let start = req
+                .headers()
+                .get(header::RANGE)
+                .and_then(|r| r.to_str().ok())
+                .and_then(|r| r.strip_prefix("bytes="))
+                .and_then(|r| r.trim_end_matches('-').parse::<usize>().ok());
+            match start {
+                Some(start) if honor_range => Response::builder()
+                    .status(StatusCode::PARTIAL_CONTENT)
+                    .header(
+                        header::CONTENT_RANGE,
+                        format!("bytes {start}-{}/{}", DATA.len() - 1, DATA.len()),
+                    )
+                    .body(Body::from(&DATA[start..]))
+                    .unwrap(),
+                _ => Response::new(Body::from(DATA)),
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn ranged_stream_resumes_from_offset() {
+        let client = stub::client(ranged_server(true));
+
+        let stream = client
+            .get_stream_ranged("images/test/get", Some((4, None)), true)
+            .await
+            .unwrap();
+        assert_eq!(stream.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(stream.content_range(), Some("bytes 4-9/10"));
+        assert_eq!(stream.offset(), 4);
+        assert_eq!(concat(stream).await, b"456789");
+
+        let stream = client
+            .get_stream_ranged("images/test/get", None, true)
+            .await
+            .unwrap();
+        assert_eq!(stream.status(), StatusCode::OK);
+        assert_eq!(stream.offset(), 0);
+        assert_eq!(concat(stream).await, b"0123456789");
+    }
+
+    #[tokio::test]
+    async fn ranged_stream_falls_back_to_full_download() {
+        let client = stub::client(ranged_server(false));
+
+        let result = client
+            .get_stream_ranged("images/test/get", Some((4, None)), true)
+            .await;
+        assert!(matches!(result, Err(Error::RangeIgnored)));
+
+        let stream = client
+            .get_stream_ranged("images/test/get", Some((4, None)), false)
+            .await
+            .unwrap();
+        assert!(!stream.is_partial());
+        assert_eq!(stream.offset(), 0);
+        assert_eq!(concat(stream).await, b"0123456789");
+    }
+
+    #[tokio::test]
+    async fn json_errors_include_response_snippet() {
+        let addr = stub::serve(|_| async { Response::new(Body::from("{\"time\": \"now\"}\r\n")) });
+        let client = stub::client(addr);
+
+        let err = client.get_json::<Event>("events/last").await.unwrap_err();
+        assert!(
+            matches!(&err, Error::Deserialize { endpoint, snippet, .. } if endpoint == "events/last" && snippet.contains("now")),
+            "{err:?}"
+        );
+
+        let results = client
+            .get_json_stream::<Event>("events")
+            .collect::<Vec<_>>()
+            .await;
+        assert!(
+            matches!(&results[..], [Err(Error::Deserialize { endpoint, type_name, .. })] if endpoint == "events" && type_name.ends_with("Event")),
+            "{results:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn healthcheck_reports_connect_failure() {
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        let err = stub::client(addr).healthcheck("/_ping").await.unwrap_err();
+        assert!(matches!(err, Error::Connect(_)), "{err:?}");
+    }
+
+    #[tokio::test]
+    async fn upgrade_accepts_raw_stream_ok_response() {
+        let (stdin_tx, mut stdin_rx) = tokio::sync::mpsc::unbounded_channel();
+        let addr = stub::serve(move |req| {
+            let stdin_tx = stdin_tx.clone();
+            async move {
+                tokio::spawn(async move {
+                    let stdin = hyper::body::to_bytes(req.into_body()).await.unwrap();
+                    stdin_tx.send(stdin).unwrap();
+                });
+                Response::builder()
+                    .header(header::CONTENT_TYPE, "application/vnd.docker.raw-stream")
+                    .body(Body::from("hello from container"))
+                    .unwrap()
+            }
+        });
+
+        let mut stream = stub::client(addr)
+            .post_upgrade_stream("containers/test/attach", Payload::empty())
+            .await
+            .unwrap();
+
+        stream.write_all(b"stdin").await.unwrap();
+        AsyncWriteExt::close(&mut stream).await.unwrap();
+        assert_eq!(stdin_rx.recv().await.unwrap(), "stdin");
+
+        let mut out = String::new();
+        stream.read_to_string(&mut out).await.unwrap();
+        assert_eq!(out, "hello from container");
+
+        let err = stream.write_all(b"more").await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::BrokenPipe);
+    }
+
+    #[tokio::test]
+    async fn raw_stream_accepts_any_success_response() {
+        let addr = stub::serve(|req| async move {
+            if req.uri().path().contains("missing") {
+                return Response::builder()
+                    .status(StatusCode::NOT_FOUND)
+                    .body(Body::from(r#"{"message":"no such container"}"#))
+                    .unwrap();
+            }
+            Response::builder()
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from("raw output"))
+                .unwrap()
+        });
+        let client = stub::client(addr);
+
+        let mut stream = client
+            .post_raw_stream("/containers/test/attach", Payload::empty())
+            .await
+            .unwrap();
+        let mut out = String::new();
+        stream.read_to_string(&mut out).await.unwrap();
+        assert_eq!(out, "raw output");
+
+        let echo = stub::serve(|req| async move { Response::new(req.into_body()) });
+        let mut stream = stub::client(echo)
+            .post_raw_stream(
+                "/containers/test/attach",
+                Payload::Text("hello ".to_string()),
+            )
+            .await
+            .unwrap();
+        stream.write_all(b"from stdin").await.unwrap();
+        AsyncWriteExt::close(&mut stream).await.unwrap();
+        let mut out = String::new();
+        stream.read_to_string(&mut out).await.unwrap();
+        assert_eq!(out, "hello from stdin");
+
+        // the initial body is streamed, the stream is returned before it ends
+        let (mut body_tx, body) = Body::channel();
+        body_tx.send_data("hello ".into()).await.unwrap();
+        let mut stream = tokio::time::timeout(
+            Duration::from_secs(5),
+            stub::client(echo)
+                .post_raw_stream("/containers/test/attach", Payload::body_stream(body, None)),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        let mut start = [0; 6];
+        stream.read_exact(&mut start).await.unwrap();
+        assert_eq!(&start, b"hello ");
+        body_tx.send_data("world, ".into()).await.unwrap();
+        drop(body_tx);
+        stream.write_all(b"from stdin").await.unwrap();
+        AsyncWriteExt::close(&mut stream).await.unwrap();
+        let mut out = String::new();
+        stream.read_to_string(&mut out).await.unwrap();
+        assert_eq!(out, "world, from stdin");
+
+        let err = client
+            .post_raw_stream("/containers/missing/attach", Payload::empty())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Fault {
+                code: StatusCode::NOT_FOUND,
+                ..
+            }
+        ));
+    }
+
+    /// Upgrades a connection over a unix socket pair and returns the client side of it together
+    /// with the data the server side received until it observed EOF.
+    #[cfg(unix)]
+    async fn upgraded_socket_pair() -> (
+        conn::UpgradedStream,
+        tokio::sync::oneshot::Receiver<Vec<u8>>,
+    ) {
+        use hyper::{server::conn::Http, service::service_fn};
+        use tokio::io::AsyncReadExt;
+
+        let (client, server) = tokio::net::UnixStream::pair().unwrap();
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        let tx = std::sync::Mutex::new(Some(tx));
+        let service = service_fn(move |mut req: hyper::Request<Body>| {
+            let tx = tx.lock().unwrap().take();
+            async move {
+                tokio::spawn(async move {
+                    let mut upgraded = hyper::upgrade::on(&mut req).await.unwrap();
+                    let mut data = vec![];
+                    upgraded.read_to_end(&mut data).await.unwrap();
+                    let _ = tx.unwrap().send(data);
+                });
+                Response::builder()
+                    .status(StatusCode::SWITCHING_PROTOCOLS)
+                    .header(header::CONNECTION, "Upgrade")
+                    .header(header::UPGRADE, "tcp")
+                    .body(Body::empty())
+            }
+        });
+        tokio::spawn(
+            Http::new()
+                .serve_connection(server, service)
+                .with_upgrades(),
+        );
+
+        let client = RequestClient::<Error>::with_validator(
+            conn::Transport::from_stream("socketpair", client),
+            |resp| Box::pin(async move { Ok(resp) }),
+        );
+        let stream = client
+            .post_upgrade_stream("containers/test/attach", Payload::empty())
+            .await
+            .unwrap();
+        (stream, rx)
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn upgraded_stream_shuts_down_on_close_and_drop() {
+        use crate::conn::{decode_chunk, Multiplexer};
+        use futures_util::AsyncWriteExt;
+        use std::time::Duration;
+
+        let timeout = Duration::from_secs(1);
+
+        let (mut stream, rx) = upgraded_socket_pair().await;
+        stream.write_all(b"stdin").await.unwrap();
+        stream.close().await.unwrap();
+        let data = tokio::time::timeout(timeout, rx).await.unwrap().unwrap();
+        assert_eq!(data, b"stdin");
+
+        let (mut stream, rx) = upgraded_socket_pair().await;
+        stream.write_all(b"dropped").await.unwrap();
+        drop(stream);
+        let data = tokio::time::timeout(timeout, rx).await.unwrap().unwrap();
+        assert_eq!(data, b"dropped");
+
+        let (stream, rx) = upgraded_socket_pair().await;
+        let mut multiplexer = Multiplexer::new(stream, decode_chunk);
+        multiplexer.write_stdin_framed(b"framed").await.unwrap();
+        multiplexer.close().await.unwrap();
+        let data = tokio::time::timeout(timeout, rx).await.unwrap().unwrap();
+        assert_eq!(&data[8..], b"framed");
+    }
+
+    #[tokio::test]
+    async fn upgrade_rejects_other_ok_responses() {
+        let addr = stub::serve(|_| async {
+            Response::builder()
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from("{}"))
+                .unwrap()
+        });
+
+        let result = stub::client(addr)
+            .post_upgrade_stream("containers/test/attach", Payload::empty())
+            .await;
+        assert!(matches!(result, Err(Error::ConnectionNotUpgraded)));
+    }
+
+    #[tokio::test]
+    async fn merges_default_headers_into_every_request() {
+        let received = Arc::new(Mutex::new(Vec::<HeaderMap>::new()));
+        let sink = received.clone();
+        let addr = stub::serve(move |req| {
+            sink.lock().unwrap().push(req.headers().clone());
+            async { Response::new(Body::from("{}")) }
+        });
+
+        let mut defaults = Headers::single("User-Agent", "containers-api");
+        defaults.add("Accept", "application/json");
+        defaults.add("Connection", "close");
+        defaults.add("Upgrade", "h2c");
+        let client = stub::client(addr).with_default_headers(defaults);
+
+        client.get_string("/info").await.unwrap();
+        let _: serde_json::Value = client
+            .get_json_with_headers(
+                "/distribution/alpine/json",
+                Some(Headers::single("X-Registry-Auth", "token")),
+            )
+            .await
+            .unwrap();
+        client
+            .delete_with_headers(
+                "/images/alpine",
+                Some(Headers::single(
+                    "accept",
+                    "application/vnd.oci.image.manifest.v1+json",
+                )),
+            )
+            .await
+            .unwrap();
+        let result = client
+            .post_upgrade_stream("/containers/test/attach", Payload::empty())
+            .await;
+        assert!(matches!(result, Err(Error::ConnectionNotUpgraded)));
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 4);
+        for headers in received.iter() {
+            assert_eq!(headers[header::USER_AGENT], "containers-api");
+        }
+        assert_eq!(received[0][header::ACCEPT], "application/json");
+        assert_eq!(received[1][header::ACCEPT], "application/json");
+        assert_eq!(received[1]["x-registry-auth"], "token");
+        assert_eq!(
+            received[2]
+                .get_all(header::ACCEPT)
+                .iter()
+                .collect::<Vec<_>>(),
+            ["application/vnd.oci.image.manifest.v1+json"]
+        );
+        assert_eq!(received[3][header::CONNECTION], "Upgrade");
+        assert_eq!(received[3][header::UPGRADE], "tcp");
+    }
+
+    #[tokio::test]
+    async fn head_returns_response_headers() {
+        let addr = stub::serve(|req| async move {
+            assert_eq!(req.method(), hyper::Method::HEAD);
+            Response::builder()
+                .header(header::CONTENT_LENGTH, "4096")
+                .header("docker-content-digest", "sha256:abc")
+                .body(Body::empty())
+                .unwrap()
+        });
+
+        let headers = stub::client(addr)
+            .head_headers("/images/alpine/get")
+            .await
+            .unwrap();
+        assert_eq!(headers[header::CONTENT_LENGTH], "4096");
+        assert_eq!(headers["docker-content-digest"], "sha256:abc");
+    }
+
+    #[tokio::test]
+    async fn no_content_deserializes_to_default() {
+        let addr = stub::serve(|req| async move {
+            let path = req.uri().path();
+            let (status, body) = if path.ends_with("/empty") {
+                (StatusCode::NO_CONTENT, "")
+            } else if path.ends_with("/missing") {
+                (StatusCode::NOT_FOUND, r#"{"message":"no such container"}"#)
+            } else {
+                (StatusCode::OK, r#"[{"time": 3}]"#)
+            };
+            Response::builder()
+                .status(status)
+                .body(Body::from(body))
+                .unwrap()
+        });
+        let client = stub::client(addr);
+
+        let events: Vec<Event> = client.get_json_or_empty("/events/empty").await.unwrap();
+        assert!(events.is_empty());
+        let events: Vec<Event> = client.get_json_or_empty("/events/all").await.unwrap();
+        assert_eq!(events[0].time, 3);
+        let err = client
+            .get_json_or_empty::<Vec<Event>>("/events/missing")
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&err, Error::Fault { code: StatusCode::NOT_FOUND, message, .. } if message == "no such container")
+        );
+    }
+
+    #[tokio::test]
+    async fn closure_validator_is_called_for_every_response() {
+        let addr = stub::serve(|req| async move {
+            let status = if req.uri().path().ends_with("missing") {
+                StatusCode::NOT_FOUND
+            } else {
+                StatusCode::OK
+            };
+            Response::builder()
+                .status(status)
+                .body(Body::from("{}"))
+                .unwrap()
+        });
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let client = RequestClient::<Error>::with_validator(stub::transport(addr), move |resp| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async move {
+                match resp.status() {
+                    StatusCode::NOT_FOUND => Err(Error::Fault {
+                        code: StatusCode::NOT_FOUND,
+                        message: "not found".into(),
+                        body: Bytes::new(),
+                    }),
+                    _ => Ok(resp),
+                }
+            })
+        });
+
+        client.get_string("info").await.unwrap();
+        client.clone().get_string("version").await.unwrap();
+        let err = client.get_string("missing").await.unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Fault {
+                code: StatusCode::NOT_FOUND,
+                ..
+            }
+        ));
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert!(format!("{client:?}").contains("<validator>"));
+    }
+
+    #[tokio::test]
+    async fn http2_json_roundtrip() {
+        let addr = stub::serve_h2(|req| async move {
+            assert_eq!(req.version(), hyper::Version::HTTP_2);
+            Response::new(Body::from(r#"{"time": 42}"#))
+        });
+
+        let client = stub::client_h2(addr);
+        let event: Event = client.get_json("/events/last").await.unwrap();
+        assert_eq!(event.time, 42);
+
+        let chunks = client
+            .get_stream("/logs")
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(chunks.concat(), br#"{"time": 42}"#);
+    }
+
+    #[tokio::test]
+    async fn http2_upgrade_is_not_supported() {
+        let addr = stub::serve_h2(|_| async { Response::new(Body::empty()) });
+
+        let result = stub::client_h2(addr)
+            .post_upgrade_stream("containers/test/attach", Payload::empty())
+            .await;
+        assert!(matches!(result, Err(Error::UpgradeNotSupported)));
+    }
+
+    #[tokio::test]
+    async fn raw_response_exposes_trailers() {
+        let addr = stub::serve_h2(|req| async move {
+            if req.uri().path().ends_with("missing") {
+                return Response::builder()
+                    .status(StatusCode::NOT_FOUND)
+                    .body(Body::from("no such container"))
+                    .unwrap();
+            }
+            let (mut tx, body) = Body::channel();
+            tokio::spawn(async move {
+                tx.send_data(Bytes::from("{\"read\":1}")).await.unwrap();
+                let mut trailers = hyper::HeaderMap::new();
+                trailers.insert("x-checksum", "abc".parse().unwrap());
+                tx.send_trailers(trailers).await.unwrap();
+            });
+            Response::builder()
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(body)
+                .unwrap()
+        });
+        let client = stub::client_h2(addr);
+
+        let response = client.get_raw("/containers/test/stats").await.unwrap();
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+        let mut body = response.into_body();
+        let trailers = conn::read_trailers(&mut body).await.unwrap().unwrap();
+        assert_eq!(trailers["x-checksum"], "abc");
+
+        let response = client
+            .post_raw("/images/test/push", Payload::empty(), None)
+            .await
+            .unwrap();
+        let trailers = conn::read_trailers(&mut response.into_body())
+            .await
+            .unwrap();
+        assert!(trailers.is_some());
+
+        let err = client.get_raw("/containers/missing").await.unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Fault { code: StatusCode::NOT_FOUND, message, .. } if message == "no such container"
+        ));
+    }
+
+    #[tokio::test]
+    async fn put_json_deserializes_response() {
+        let addr = stub::serve(|req| async move {
+            let path = req.uri().path();
+            let body = if path.ends_with("/volumes/ok") {
+                r#"{"time": 7}"#
+            } else if path.ends_with("/volumes/text") {
+                "updated"
+            } else {
+                ""
+            };
+            Response::new(Body::from(body))
+        });
+        let client = stub::client(addr);
+
+        let event: Event = client
+            .put_json("/volumes/ok", Payload::Json("{}"))
+            .await
+            .unwrap();
+        assert_eq!(event.time, 7);
+
+        let err = client
+            .put_json::<_, Event>("/volumes/text", Payload::Json("{}"))
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&err, Error::Deserialize { endpoint, source, .. } if endpoint == "/volumes/text" && source.is_syntax()),
+            "{err:?}"
+        );
+
+        let err = client
+            .put_json::<_, Event>("/volumes/empty", Payload::empty())
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&err, Error::Deserialize { source, .. } if source.is_eof()),
+            "{err:?}"
+        );
+    }
+
+    #[cfg(feature = "opentelemetry")]
+    #[tokio::test]
+    async fn traces_requests() {
+        use opentelemetry::{trace::TracerProvider, Value};
+        use opentelemetry_sdk::{
+            propagation::TraceContextPropagator,
+            trace::{InMemorySpanExporter, SdkTracerProvider},
+        };
+
+        opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+        let exporter = InMemorySpanExporter::default();
+        let provider = SdkTracerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .build();
+
+        let addr = stub::serve(|req| async move {
+            let traceparent = req.headers().get("traceparent").cloned();
+            let status = if req.uri().path().ends_with("missing") {
+                StatusCode::NOT_FOUND
+            } else {
+                StatusCode::OK
+            };
+            Response::builder()
+                .status(status)
+                .body(Body::from(format!("{traceparent:?}")))
+                .unwrap()
+        });
+        let client = stub::client(addr).with_tracer(provider.tracer("test"));
+
+        let body = client.get_string("/containers/json").await.unwrap();
+        assert!(body.starts_with("Some(\"00-"), "{body}");
+        client.get_string("/containers/missing").await.unwrap_err();
+
+        let spans = exporter.get_finished_spans().unwrap();
+        assert_eq!(spans.len(), 2);
+        let attr = |span: &opentelemetry_sdk::trace::SpanData, key: &str| {
+            span.attributes
+                .iter()
+                .find(|kv| kv.key.as_str() == key)
+                .map(|kv| kv.value.clone())
+        };
+        assert_eq!(
+            attr(&spans[0], "http.request.method"),
+            Some(Value::from("GET"))
+        );
+        assert!(attr(&spans[0], "url.path")
+            .unwrap()
+            .as_str()
+            .ends_with("/containers/json"));
+        assert_eq!(
+            attr(&spans[0], "http.response.status_code"),
+            Some(Value::I64(200))
+        );
+        assert!(body.contains(&spans[0].span_context.trace_id().to_string()));
+        assert_eq!(spans[0].status, opentelemetry::trace::Status::Unset);
+        assert_eq!(
+            attr(&spans[1], "http.response.status_code"),
+            Some(Value::I64(404))
+        );
+        assert!(matches!(
+            spans[1].status,
+            opentelemetry::trace::Status::Error { .. }
+        ));
+    }
+}
@@ -51,6 +51,8 @@ where
     fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
         self.project().tokio_multiplexer.poll_flush(cx)
     }
+    /// Shuts down the write half only. The read half is closed when the inner stream is
+    /// dropped.
     fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
         self.project().tokio_multiplexer.poll_shutdown(cx)
     }
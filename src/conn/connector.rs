@@ -0,0 +1,176 @@
+//! Connectors used by [`Transport::Custom`](crate::conn::Transport::Custom).
+
+use hyper::{
+    client::connect::{Connected, Connection},
+    service::Service,
+    Uri,
+};
+use std::{
+    fmt,
+    future::Future,
+    io,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+trait Io: AsyncRead + AsyncWrite + Send + 'static {}
+
+impl<T: AsyncRead + AsyncWrite + Send + 'static> Io for T {}
+
+/// A connection established by a [`CustomConnector`].
+pub struct CustomStream(Pin<Box<dyn Io>>);
+
+impl CustomStream {
+    pub fn new<S>(stream: S) -> Self
+    where
+        S: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        Self(Box::pin(stream))
+    }
+}
+
+impl fmt::Debug for CustomStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CustomStream").finish_non_exhaustive()
+    }
+}
+
+impl AsyncRead for CustomStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        self.0.as_mut().poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for CustomStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.0.as_mut().poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.0.as_mut().poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.0.as_mut().poll_shutdown(cx)
+    }
+}
+
+impl Connection for CustomStream {
+    fn connected(&self) -> Connected {
+        Connected::new()
+    }
+}
+
+type ConnectFuture = Pin<Box<dyn Future<Output = io::Result<CustomStream>> + Send>>;
+type ConnectFn = dyn Fn() -> ConnectFuture + Send + Sync;
+
+/// A connector establishing connections with a caller supplied function instead of connecting
+/// to an address. The URI of the request is ignored.
+#[derive(Clone)]
+pub struct CustomConnector {
+    connect: Arc<ConnectFn>,
+}
+
+impl fmt::Debug for CustomConnector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CustomConnector").finish_non_exhaustive()
+    }
+}
+
+impl CustomConnector {
+    /// Creates a connector calling `connect` every time a new connection is needed.
+    pub fn new<F, Fut, S>(connect: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = io::Result<S>> + Send + 'static,
+        S: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        Self {
+            connect: Arc::new(move || {
+                let fut = connect();
+                Box::pin(async move { fut.await.map(CustomStream::new) })
+            }),
+        }
+    }
+
+    /// Creates a connector that hands out `stream`, for example a socket received through
+    /// systemd socket activation. The stream can only be used once, so every connection after the
+    /// first one fails.
+    pub fn from_stream<S>(stream: S) -> Self
+    where
+        S: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        let stream = Arc::new(Mutex::new(Some(CustomStream::new(stream))));
+        Self {
+            connect: Arc::new(move || {
+                let stream = stream.lock().ok().and_then(|mut stream| stream.take());
+                Box::pin(async move {
+                    stream.ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::NotConnected,
+                            "the pre-connected stream was already used",
+                        )
+                    })
+                })
+            }),
+        }
+    }
+
+    #[cfg(feature = "test-util")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "test-util")))]
+    /// Creates a connector serving every connection in memory by answering each request with
+    /// `handler`. Useful for testing without a running daemon.
+    pub fn in_memory<F, Fut>(handler: F) -> Self
+    where
+        F: Fn(hyper::Request<hyper::Body>) -> Fut + Clone + Send + Sync + 'static,
+        Fut: Future<Output = hyper::Response<hyper::Body>> + Send + 'static,
+    {
+        use hyper::service::service_fn;
+        use std::convert::Infallible;
+
+        Self::new(move || {
+            let handler = handler.clone();
+            async move {
+                let (client, server) = tokio::io::duplex(64 * 1024);
+                let service = service_fn(move |req| {
+                    let fut = handler(req);
+                    async move { Ok::<_, Infallible>(fut.await) }
+                });
+                tokio::spawn(async move {
+                    if let Err(e) = hyper::server::conn::Http::new()
+                        .serve_connection(server, service)
+                        .with_upgrades()
+                        .await
+                    {
+                        log::trace!("in memory connection failed - {e}");
+                    }
+                });
+                Ok(client)
+            }
+        })
+    }
+}
+
+impl Service<Uri> for CustomConnector {
+    type Response = CustomStream;
+    type Error = io::Error;
+    type Future = ConnectFuture;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, _: Uri) -> Self::Future {
+        (self.connect)()
+    }
+}
@@ -1,6 +1,8 @@
 #[derive(Debug, Default, Clone)]
-/// Helper structure used as a container for HTTP headers passed to a request
-pub struct Headers(Vec<(&'static str, String)>);
+/// Helper structure used as a container for HTTP headers passed to a request.
+///
+/// Header names are case-insensitive, they are stored in lowercase.
+pub struct Headers(Vec<(String, String)>);
 
 impl Headers {
     /// Shortcut for when one does not want headers in a request
@@ -8,31 +10,126 @@ impl Headers {
         None
     }
 
-    /// Adds a single key=value header pair
-    pub fn add<V>(&mut self, key: &'static str, val: V)
+    /// Adds a single key=value header pair. Existing values of the header are kept.
+    pub fn add<K, V>(&mut self, key: K, val: V)
     where
+        K: Into<String>,
         V: Into<String>,
     {
+        self.0.push((normalize(key), val.into()))
+    }
+
+    /// Sets the value of a header replacing all existing values of it.
+    pub fn set<K, V>(&mut self, key: K, val: V)
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        let key = normalize(key);
+        self.0.retain(|(k, _)| *k != key);
         self.0.push((key, val.into()))
     }
 
+    /// Returns the first value of the header `key`.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.as_str())
+    }
+
     /// Constructs an instance of Headers with initial pair, usually used when there is only
     /// a need for one header.
-    pub fn single<V>(key: &'static str, val: V) -> Self
+    pub fn single<K, V>(key: K, val: V) -> Self
     where
+        K: Into<String>,
         V: Into<String>,
     {
         let mut h = Self::default();
         h.add(key, val);
         h
     }
+
+    /// Appends all of `headers`, replacing existing values of the headers it contains.
+    pub(crate) fn override_with(&mut self, headers: Headers) {
+        self.0
+            .retain(|(k, _)| !headers.0.iter().any(|(key, _)| key == k));
+        self.0.extend(headers.0)
+    }
+}
+
+fn normalize<K: Into<String>>(key: K) -> String {
+    let mut key = key.into();
+    key.make_ascii_lowercase();
+    key
 }
 
 impl IntoIterator for Headers {
-    type Item = (&'static str, String);
+    type Item = (String, String);
     type IntoIter = std::vec::IntoIter<Self::Item>;
 
     fn into_iter(self) -> Self::IntoIter {
         self.0.into_iter()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Headers;
+
+    fn pairs(headers: Headers) -> Vec<(String, String)> {
+        headers.into_iter().collect()
+    }
+
+    #[test]
+    fn compares_keys_case_insensitively() {
+        let mut headers = Headers::single("Content-Type", "application/json");
+        assert_eq!(headers.get("content-type"), Some("application/json"));
+        assert_eq!(headers.get("CONTENT-TYPE"), Some("application/json"));
+
+        headers.add("X-Registry-Auth", "first");
+        headers.add("x-registry-auth", "second");
+        assert_eq!(headers.get("X-REGISTRY-AUTH"), Some("first"));
+
+        headers.set("content-TYPE", "application/x-tar");
+        assert_eq!(
+            pairs(headers),
+            [
+                ("x-registry-auth".to_string(), "first".to_string()),
+                ("x-registry-auth".to_string(), "second".to_string()),
+                ("content-type".to_string(), "application/x-tar".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn overrides_keys_present_in_other_headers() {
+        let mut defaults = Headers::single("User-Agent", "containers-api");
+        defaults.add("Accept", "application/json");
+        defaults.add("Accept", "text/plain");
+
+        let mut headers = Headers::single("accept", "application/vnd.oci.image.manifest.v1+json");
+        headers.add(
+            "Accept",
+            "application/vnd.docker.distribution.manifest.v2+json",
+        );
+        headers.add("X-Registry-Auth", "token");
+        defaults.override_with(headers);
+
+        assert_eq!(
+            pairs(defaults),
+            [
+                ("user-agent".to_string(), "containers-api".to_string()),
+                (
+                    "accept".to_string(),
+                    "application/vnd.oci.image.manifest.v1+json".to_string()
+                ),
+                (
+                    "accept".to_string(),
+                    "application/vnd.docker.distribution.manifest.v2+json".to_string()
+                ),
+                ("x-registry-auth".to_string(), "token".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,337 @@
+use crate::conn::{Error, Result};
+use futures_util::stream::Stream;
+use hyper::body::Bytes;
+use pin_project::pin_project;
+use serde::de::DeserializeOwned;
+use std::{
+    collections::VecDeque,
+    fmt,
+    marker::PhantomData,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+/// Maximum length of the part of the body included in a deserialization error.
+const SNIPPET_LEN: usize = 512;
+
+/// Deserializes `data` received from `endpoint` as `T`. On failure the returned error contains
+/// the part of `data` around the location of the failure.
+pub(crate) fn from_slice<T: DeserializeOwned>(endpoint: &str, data: &[u8]) -> Result<T> {
+    serde_json::from_slice(data).map_err(|e| deserialize_error::<T>(endpoint, data, e))
+}
+
+/// Wraps a serde error that occurred while deserializing `data` received from `endpoint`.
+pub(crate) fn deserialize_error<T>(
+    endpoint: &str,
+    data: &[u8],
+    source: serde_json::Error,
+) -> Error {
+    Error::Deserialize {
+        endpoint: endpoint.to_string(),
+        type_name: std::any::type_name::<T>(),
+        snippet: snippet(data, error_offset(data, &source)),
+        source,
+    }
+}
+
+/// Converts the line and column reported by serde to a byte offset within `data`.
+fn error_offset(data: &[u8], err: &serde_json::Error) -> usize {
+    let line_start: usize = data
+        .split(|b| *b == b'\n')
+        .take(err.line().saturating_sub(1))
+        .map(|line| line.len() + 1)
+        .sum();
+    (line_start + err.column().saturating_sub(1)).min(data.len())
+}
+
+/// Returns at most [`SNIPPET_LEN`] bytes of `data` centered around `offset`.
+fn snippet(data: &[u8], offset: usize) -> String {
+    let end = (offset.saturating_sub(SNIPPET_LEN / 2) + SNIPPET_LEN).min(data.len());
+    let start = end.saturating_sub(SNIPPET_LEN);
+    String::from_utf8_lossy(&data[start..end]).into_owned()
+}
+
+/// A stream of JSON values deserialized from chunks of a response body, returned by
+/// [`RequestClient::get_json_stream`](crate::conn::RequestClient::get_json_stream) and
+/// [`RequestClient::post_into_stream`](crate::conn::RequestClient::post_into_stream).
+///
+/// Values are deserialized one at a time as the stream is polled, a chunk containing many values
+/// is not deserialized all at once. Use [`buffered_items`](JsonStream::buffered_items) to read
+/// ahead.
+#[pin_project]
+pub struct JsonStream<S, T, E> {
+    #[pin]
+    stream: S,
+    endpoint: Arc<str>,
+    /// The chunk being deserialized and the offset of the next value within it.
+    chunk: Option<(Bytes, usize)>,
+    buffer: VecDeque<std::result::Result<T, E>>,
+    prefetch: usize,
+    done: bool,
+    _type: PhantomData<fn() -> T>,
+}
+
+impl<S, T, E> fmt::Debug for JsonStream<S, T, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JsonStream")
+            .field("endpoint", &self.endpoint)
+            .field("buffered", &self.buffer.len())
+            .field("prefetch", &self.prefetch)
+            .finish()
+    }
+}
+
+impl<S, T, E> JsonStream<S, T, E> {
+    pub(crate) fn new(endpoint: impl Into<Arc<str>>, stream: S) -> Self {
+        Self {
+            stream,
+            endpoint: endpoint.into(),
+            chunk: None,
+            buffer: VecDeque::new(),
+            prefetch: 0,
+            done: false,
+            _type: PhantomData,
+        }
+    }
+
+    /// Deserializes up to `n` values that were already received ahead of the one being polled
+    /// so that following polls return without waiting. At most `n` values are held at a time,
+    /// values are not read ahead by default.
+    pub fn buffered_items(mut self, n: usize) -> Self {
+        self.prefetch = n;
+        self
+    }
+
+    /// Number of values read ahead that were not yet returned.
+    pub fn buffered_len(&self) -> usize {
+        self.buffer.len()
+    }
+}
+
+impl<S, T, E> JsonStream<S, T, E>
+where
+    S: Stream<Item = std::result::Result<Bytes, E>>,
+    T: DeserializeOwned,
+    E: From<Error>,
+{
+    /// Deserializes the next value, polling the inner stream for a new chunk if the current one
+    /// has no values left.
+    fn poll_value(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<std::result::Result<T, E>>> {
+        let mut this = self.project();
+        loop {
+            if let Some((chunk, offset)) = this.chunk.as_mut() {
+                let data = &chunk[*offset..];
+                let mut values = serde_json::Deserializer::from_slice(data).into_iter::<T>();
+                match values.next() {
+                    Some(Ok(value)) => {
+                        *offset += values.byte_offset();
+                        return Poll::Ready(Some(Ok(value)));
+                    }
+                    Some(Err(e)) => {
+                        let err = deserialize_error::<T>(this.endpoint, data, e);
+                        *this.chunk = None;
+                        return Poll::Ready(Some(Err(E::from(err))));
+                    }
+                    None => *this.chunk = None,
+                }
+            }
+
+            match futures_util::ready!(this.stream.as_mut().poll_next(cx)) {
+                Some(Ok(chunk)) => *this.chunk = Some((chunk, 0)),
+                Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+                None => return Poll::Ready(None),
+            }
+        }
+    }
+}
+
+impl<S, T, E> Stream for JsonStream<S, T, E>
+where
+    S: Stream<Item = std::result::Result<Bytes, E>>,
+    T: DeserializeOwned,
+    E: From<Error>,
+{
+    type Item = std::result::Result<T, E>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.prefetch == 0 && self.buffer.is_empty() {
+            return self.poll_value(cx);
+        }
+
+        while !self.done && self.buffer.len() < self.prefetch {
+            match self.as_mut().poll_value(cx) {
+                Poll::Ready(Some(value)) => self.as_mut().project().buffer.push_back(value),
+                Poll::Ready(None) => *self.as_mut().project().done = true,
+                Poll::Pending => break,
+            }
+        }
+
+        match self.as_mut().project().buffer.pop_front() {
+            Some(value) => Poll::Ready(Some(value)),
+            None if self.done => Poll::Ready(None),
+            None => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{from_slice, JsonStream, SNIPPET_LEN};
+    use crate::conn::Error;
+    use futures_util::{stream, StreamExt, TryStreamExt};
+    use hyper::body::Bytes;
+    use serde::{Deserialize, Deserializer};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Number of [`Progress`] values deserialized so far.
+    static DESERIALIZED: AtomicUsize = AtomicUsize::new(0);
+
+    #[derive(Debug)]
+    struct Progress(u64);
+
+    impl<'de> Deserialize<'de> for Progress {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            #[derive(Deserialize)]
+            struct Raw {
+                current: u64,
+            }
+            let raw = Raw::deserialize(deserializer)?;
+            DESERIALIZED.fetch_add(1, Ordering::SeqCst);
+            Ok(Progress(raw.current))
+        }
+    }
+
+    /// Chunks of 100 progress messages each, `n` messages in total.
+    fn progress_chunks(n: u64) -> Vec<Result<Bytes, Error>> {
+        (0..n)
+            .collect::<Vec<_>>()
+            .chunks(100)
+            .map(|chunk| {
+                let messages = chunk
+                    .iter()
+                    .map(|i| format!("{{\"status\":\"Downloading\",\"current\":{i}}}\r\n"))
+                    .collect::<String>();
+                Ok(Bytes::from(messages))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn snippet_centers_on_failure() {
+        let mut data = String::from("[");
+        data.push_str(&"1,".repeat(1000));
+        let offset = data.len();
+        data.push_str("oops,");
+        data.push_str(&"2,".repeat(1000));
+        data.push_str("3]");
+
+        let err = from_slice::<Vec<u32>>("/containers/json", data.as_bytes()).unwrap_err();
+        match err {
+            Error::Deserialize {
+                endpoint,
+                type_name,
+                snippet,
+                ..
+            } => {
+                assert_eq!(endpoint, "/containers/json");
+                assert_eq!(type_name, "alloc::vec::Vec<u32>");
+                assert_eq!(snippet.len(), SNIPPET_LEN);
+                let pos = snippet.find("oops").unwrap() as isize;
+                let center = (SNIPPET_LEN / 2) as isize;
+                assert!((pos - center).abs() <= 2, "{pos}");
+                assert_eq!(&data[offset - center as usize..][..SNIPPET_LEN], snippet);
+            }
+            e => panic!("unexpected error {e:?}"),
+        }
+    }
+
+    #[test]
+    fn snippet_is_capped_and_lossy() {
+        let mut data = vec![0xff; 2000];
+        data.extend_from_slice(b"{}");
+
+        let err = from_slice::<Vec<u32>>("/images/json", &data).unwrap_err();
+        match err {
+            Error::Deserialize { snippet, .. } => {
+                assert!(snippet.chars().count() <= SNIPPET_LEN);
+                assert!(snippet.starts_with('\u{FFFD}'));
+            }
+            e => panic!("unexpected error {e:?}"),
+        }
+
+        let err = from_slice::<Vec<u32>>("/images/json", b"[1, 2").unwrap_err();
+        match err {
+            Error::Deserialize { snippet, .. } => assert_eq!(snippet, "[1, 2"),
+            e => panic!("unexpected error {e:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn deserializes_values_on_demand() {
+        const N: u64 = 100_000;
+
+        let mut values = JsonStream::<_, Progress, Error>::new(
+            "/images/create",
+            stream::iter(progress_chunks(N)),
+        );
+        let start = DESERIALIZED.load(Ordering::SeqCst);
+        for i in 0..N {
+            let value = values.next().await.unwrap().unwrap();
+            assert_eq!(value.0, i);
+            assert_eq!(DESERIALIZED.load(Ordering::SeqCst) - start, i as usize + 1);
+            assert_eq!(values.buffered_len(), 0);
+        }
+        assert!(values.next().await.is_none());
+
+        let mut values = JsonStream::<_, Progress, Error>::new(
+            "/images/create",
+            stream::iter(progress_chunks(N)),
+        )
+        .buffered_items(16);
+        let start = DESERIALIZED.load(Ordering::SeqCst);
+        let mut peak = 0;
+        for i in 0..N {
+            let value = values.next().await.unwrap().unwrap();
+            assert_eq!(value.0, i);
+            let read_ahead = DESERIALIZED.load(Ordering::SeqCst) - start - (i as usize + 1);
+            assert!(read_ahead <= 16, "{read_ahead}");
+            assert_eq!(read_ahead, values.buffered_len());
+            peak = peak.max(read_ahead);
+        }
+        assert_eq!(peak, 15);
+        assert!(values.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn stops_deserializing_chunk_on_error() {
+        let chunks: Vec<Result<Bytes, Error>> = vec![
+            Ok(Bytes::from(
+                "{\"current\":1}\r\n{\"current\":oops}\r\n{\"current\":2}",
+            )),
+            Ok(Bytes::from("{\"current\":3}\r\n")),
+        ];
+        let values =
+            JsonStream::<_, serde_json::Value, Error>::new("/images/create", stream::iter(chunks))
+                .collect::<Vec<_>>()
+                .await;
+        assert_eq!(values.len(), 3);
+        assert_eq!(values[0].as_ref().unwrap()["current"], 1);
+        assert!(
+            matches!(&values[1], Err(Error::Deserialize { snippet, .. }) if snippet.trim_start().starts_with("{\"current\":oops}"))
+        );
+        assert_eq!(values[2].as_ref().unwrap()["current"], 3);
+
+        let err = JsonStream::<_, serde_json::Value, Error>::new(
+            "/images/create",
+            stream::iter(vec![Err(Error::ConnectionNotUpgraded)]),
+        )
+        .try_collect::<Vec<_>>()
+        .await
+        .unwrap_err();
+        assert!(matches!(err, Error::ConnectionNotUpgraded));
+    }
+}
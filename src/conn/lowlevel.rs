@@ -0,0 +1,489 @@
+//! Building blocks used by [`RequestClient`](crate::conn::RequestClient), for requests it
+//! doesn't cover, like custom methods or manual redirect handling.
+
+use crate::conn::{
+    Error, Headers, Payload, Result, Transport, ValidateResponseFuture, MAX_FAULT_BODY_SIZE,
+};
+
+use futures_util::{
+    stream::{self, Stream},
+    StreamExt,
+};
+use hyper::{
+    body::Bytes,
+    header::{self, HeaderValue},
+    Body, Method, Request, Response,
+};
+
+/// Builds an HTTP request to `uri` with `body` and `headers`.
+///
+/// The `Host` header is set to an empty value unless one is given in `headers`. Otherwise hyper
+/// derives it from `uri`, which for Unix sockets contains the hex encoded socket path.
+///
+/// The `Content-Type` header is set to the mime type of `body`, if it has one, unless one is
+/// given in `headers`. [`Payload::None`] results in an empty body.
+pub fn build_request<B>(
+    method: Method,
+    uri: hyper::Uri,
+    body: Payload<B>,
+    headers: Option<Headers>,
+) -> Result<Request<Body>>
+where
+    B: Into<Body>,
+{
+    let mut req = Request::builder().method(method).uri(&uri);
+    if let Some(h) = headers {
+        for (k, v) in h.into_iter() {
+            req = req.header(k, v);
+        }
+    }
+
+    let mime = body.mime_type();
+    let mut req = req.body(body.into_body().unwrap_or_else(Body::empty))?;
+
+    let req_headers = req.headers_mut();
+    req_headers
+        .entry(header::HOST)
+        .or_insert_with(|| HeaderValue::from_static(""));
+    if let Some(mime) = mime {
+        if !req_headers.contains_key(header::CONTENT_TYPE) {
+            let value = HeaderValue::from_str(mime.as_ref()).map_err(hyper::http::Error::from)?;
+            req_headers.insert(header::CONTENT_TYPE, value);
+        }
+    }
+
+    Ok(req)
+}
+
+/// Sends `request` through `transport` and passes the response to `validator`, the same way
+/// [`RequestClient`](crate::conn::RequestClient) does for each of its requests.
+pub async fn send_and_validate<E, F>(
+    transport: &Transport,
+    request: Request<Body>,
+    validator: &F,
+) -> std::result::Result<Response<Body>, E>
+where
+    E: From<Error>,
+    F: Fn(Response<Body>) -> ValidateResponseFuture<E> + ?Sized,
+{
+    let response = transport.request(request).await.map_err(E::from)?;
+    validator(response).await
+}
+
+/// Turns an unsuccessful `response` into an [`Error::Fault`]. At most
+/// [`MAX_FAULT_BODY_SIZE`] bytes of the body are kept. The message is taken from the `message`
+/// field of a JSON body, as returned by Docker and Podman, or is the body itself otherwise.
+/// If reading the body fails the fault contains the part of it read until then.
+pub async fn fault_from_response(response: Response<Body>) -> Error {
+    let code = response.status();
+    let mut body = response.into_body();
+    let mut buf = Vec::new();
+    while let Some(chunk) = body.next().await {
+        match chunk {
+            Ok(chunk) => {
+                let n = (MAX_FAULT_BODY_SIZE - buf.len()).min(chunk.len());
+                buf.extend_from_slice(&chunk[..n]);
+                if buf.len() == MAX_FAULT_BODY_SIZE {
+                    break;
+                }
+            }
+            // the status is more useful than a broken body, keep what was read so far
+            Err(e) => {
+                log::trace!("failed to read the body of a fault response - {e}");
+                break;
+            }
+        }
+    }
+
+    #[derive(serde::Deserialize)]
+    struct ErrorMessage {
+        message: String,
+    }
+
+    let message = match serde_json::from_slice::<ErrorMessage>(&buf) {
+        Ok(err) => err.message,
+        Err(_) => String::from_utf8_lossy(&buf).trim().to_string(),
+    };
+    Error::Fault {
+        code,
+        message,
+        body: Bytes::from(buf),
+    }
+}
+
+/// Reads the whole body of `response` as a UTF-8 string.
+pub async fn get_response_string(response: Response<Body>) -> Result<String> {
+    let bytes = hyper::body::to_bytes(response.into_body()).await?;
+    String::from_utf8(bytes.to_vec()).map_err(Error::from)
+}
+
+/// Same as [`get_response_string`] but fails with [`Error::ResponseTooLarge`] as soon as the
+/// body, or its declared `Content-Length`, exceeds `limit` bytes. Use this with untrusted daemons.
+pub async fn get_response_string_with_limit(
+    response: Response<Body>,
+    limit: usize,
+) -> Result<String> {
+    let declared_len = response
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+    if declared_len.is_some_and(|len| len > limit as u64) {
+        return Err(Error::ResponseTooLarge { limit });
+    }
+
+    let mut buf = Vec::with_capacity(declared_len.unwrap_or_default() as usize);
+    let mut chunks = std::pin::pin!(stream_response_with_limit(response, limit));
+    while let Some(chunk) = chunks.next().await {
+        buf.extend_from_slice(&chunk?);
+    }
+    String::from_utf8(buf).map_err(Error::from)
+}
+
+/// Returns the body of `response` as a stream of chunks as they are received.
+pub fn stream_response(response: Response<Body>) -> impl Stream<Item = Result<Bytes>> {
+    stream_body(response.into_body())
+}
+
+/// Same as [`stream_response`] but the stream fails with [`Error::ResponseTooLarge`] and ends
+/// instead of returning a chunk that would make the total size exceed `limit` bytes.
+pub fn stream_response_with_limit(
+    response: Response<Body>,
+    limit: usize,
+) -> impl Stream<Item = Result<Bytes>> {
+    let mut received = 0;
+    let mut done = false;
+    stream_response(response)
+        .map(move |chunk| {
+            let chunk = chunk?;
+            received += chunk.len();
+            if received > limit {
+                return Err(Error::ResponseTooLarge { limit });
+            }
+            Ok(chunk)
+        })
+        .take_while(move |chunk| {
+            let take = !done;
+            done = matches!(chunk, Err(Error::ResponseTooLarge { .. }));
+            futures_util::future::ready(take)
+        })
+}
+
+/// Returns the body of `response` as a stream of chunks, each ending at a chunk received with a
+/// trailing `\r\n`. Use this with endpoints returning one JSON document per line.
+pub fn stream_json_response(response: Response<Body>) -> impl Stream<Item = Result<Bytes>> {
+    stream_json_body(response.into_body())
+}
+
+fn stream_body(body: Body) -> impl Stream<Item = Result<Bytes>> {
+    async fn unfold(mut body: Body) -> Option<(Result<Bytes>, Body)> {
+        body.next()
+            .await
+            .map(|chunk| (chunk.map_err(Error::from), body))
+    }
+
+    stream::unfold(body, unfold)
+}
+
+static JSON_WHITESPACE: &[u8] = b"\r\n";
+
+fn stream_json_body(body: Body) -> impl Stream<Item = Result<Bytes>> {
+    async fn unfold(mut body: Body) -> Option<(Result<Bytes>, Body)> {
+        let mut chunk = Vec::new();
+        while let Some(chnk) = body.next().await {
+            match chnk {
+                Ok(chnk) => {
+                    chunk.extend(chnk.to_vec());
+                    if chnk.ends_with(JSON_WHITESPACE) {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    return Some((Err(Error::from(e)), body));
+                }
+            }
+        }
+
+        if chunk.is_empty() {
+            return None;
+        }
+
+        Some((Ok(Bytes::from(chunk)), body))
+    }
+
+    stream::unfold(body, unfold)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        build_request, fault_from_response, get_response_string_with_limit, send_and_validate,
+        stream_response_with_limit,
+    };
+    use crate::conn::{stub, Error, Headers, Payload, ValidateResponseFuture, MAX_FAULT_BODY_SIZE};
+    use futures_util::{stream, StreamExt};
+    use hyper::{body::Bytes, header, Body, Method, Request, Response, StatusCode};
+
+    fn request<B: Into<Body>>(payload: Payload<B>, headers: Option<Headers>) -> Request<Body> {
+        build_request(
+            Method::POST,
+            "http://localhost/build".parse().unwrap(),
+            payload,
+            headers,
+        )
+        .unwrap()
+    }
+
+    fn content_type(req: &Request<Body>) -> Option<&str> {
+        req.headers()
+            .get(header::CONTENT_TYPE)
+            .map(|v| v.to_str().unwrap())
+    }
+
+    #[tokio::test]
+    async fn sets_headers_for_each_payload() {
+        let cases = [
+            (request(Payload::empty(), Headers::none()), None, ""),
+            (
+                request(Payload::Text("text"), Headers::none()),
+                None,
+                "text",
+            ),
+            (
+                request(Payload::Json("{}"), Headers::none()),
+                Some("application/json"),
+                "{}",
+            ),
+            (
+                request(Payload::XTar("tar"), Headers::none()),
+                Some("application/x-tar"),
+                "tar",
+            ),
+            (
+                request(Payload::Tar("tar"), Headers::none()),
+                Some("application/tar"),
+                "tar",
+            ),
+            (
+                request(Payload::Form("name=alpine&tag=3.19"), Headers::none()),
+                Some("application/x-www-form-urlencoded"),
+                "name=alpine&tag=3.19",
+            ),
+            (
+                request(Payload::body_stream(Body::from("data"), None), None),
+                None,
+                "data",
+            ),
+        ];
+
+        for (req, mime, body) in cases {
+            assert_eq!(content_type(&req), mime);
+            assert_eq!(req.headers().get_all(header::HOST).iter().count(), 1);
+            assert_eq!(req.headers()[header::HOST], "");
+            assert_eq!(hyper::body::to_bytes(req.into_body()).await.unwrap(), body);
+        }
+    }
+
+    #[tokio::test]
+    async fn builds_body_stream_request() {
+        let chunks: Vec<Result<_, std::io::Error>> =
+            vec![Ok(Bytes::from("first ")), Ok(Bytes::from("second"))];
+        let payload = Payload::body_stream(
+            Body::wrap_stream(stream::iter(chunks)),
+            Some("application/x-tar".parse().unwrap()),
+        );
+        assert!(!payload.is_none());
+        assert_eq!(
+            payload.mime_type().unwrap().essence_str(),
+            "application/x-tar"
+        );
+
+        let req = request(payload, Headers::none());
+        assert_eq!(content_type(&req), Some("application/x-tar"));
+        let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+        assert_eq!(body, "first second");
+
+        let payload = Payload::body_stream(Body::from("data"), None);
+        assert!(payload.mime_type().is_none());
+        let body = hyper::body::to_bytes(payload.into_body().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(body, "data");
+    }
+
+    #[test]
+    fn headers_override_defaults() {
+        let mut headers = Headers::single("Host", "docker.example.com");
+        headers.add("Content-Type", "application/vnd.custom+json");
+        headers.add("X-Registry-Auth", "token");
+        let req = request(Payload::Json("{}"), Some(headers));
+
+        assert_eq!(req.headers().get_all(header::HOST).iter().count(), 1);
+        assert_eq!(req.headers()[header::HOST], "docker.example.com");
+        assert_eq!(
+            req.headers().get_all(header::CONTENT_TYPE).iter().count(),
+            1
+        );
+        assert_eq!(content_type(&req), Some("application/vnd.custom+json"));
+        assert_eq!(req.headers()["x-registry-auth"], "token");
+
+        let req = request(
+            Payload::Text("text"),
+            Some(Headers::single("Content-Type", "text/plain")),
+        );
+        assert_eq!(content_type(&req), Some("text/plain"));
+        assert_eq!(req.headers()[header::HOST], "");
+    }
+
+    #[tokio::test]
+    async fn sends_and_validates() {
+        fn validate(response: Response<Body>) -> ValidateResponseFuture<Error> {
+            Box::pin(async move {
+                match response.status() {
+                    StatusCode::OK => Ok(response),
+                    code => Err(Error::Fault {
+                        code,
+                        message: "rejected".into(),
+                        body: Bytes::new(),
+                    }),
+                }
+            })
+        }
+
+        let addr = stub::serve(|req| async move {
+            let status = if req.method() == Method::OPTIONS {
+                StatusCode::OK
+            } else {
+                StatusCode::METHOD_NOT_ALLOWED
+            };
+            Response::builder()
+                .status(status)
+                .body(Body::empty())
+                .unwrap()
+        });
+        let transport = stub::transport(addr);
+
+        for (method, ok) in [(Method::OPTIONS, true), (Method::PATCH, false)] {
+            let req = build_request(
+                method,
+                transport.make_uri("/containers/json").unwrap(),
+                Payload::empty(),
+                Headers::none(),
+            )
+            .unwrap();
+            let result = send_and_validate(&transport, req, &validate).await;
+            assert_eq!(result.is_ok(), ok);
+        }
+    }
+
+    #[tokio::test]
+    async fn keeps_fault_body() {
+        let fault = |status: StatusCode, body: Vec<u8>| {
+            fault_from_response(
+                Response::builder()
+                    .status(status)
+                    .body(body.into())
+                    .unwrap(),
+            )
+        };
+
+        let json = br#"{"message":"No such image: alpine:3","cause":"manifest unknown"}"#;
+        match fault(StatusCode::NOT_FOUND, json.to_vec()).await {
+            Error::Fault {
+                code,
+                message,
+                body,
+            } => {
+                assert_eq!(code, StatusCode::NOT_FOUND);
+                assert_eq!(message, "No such image: alpine:3");
+                assert_eq!(body, &json[..]);
+            }
+            err => panic!("unexpected error {err:?}"),
+        }
+
+        match fault(
+            StatusCode::BAD_GATEWAY,
+            b"<html>bad gateway</html>\n".to_vec(),
+        )
+        .await
+        {
+            Error::Fault { message, .. } => assert_eq!(message, "<html>bad gateway</html>"),
+            err => panic!("unexpected error {err:?}"),
+        }
+
+        match fault(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            vec![b'a'; MAX_FAULT_BODY_SIZE * 2],
+        )
+        .await
+        {
+            Error::Fault { body, .. } => assert_eq!(body.len(), MAX_FAULT_BODY_SIZE),
+            err => panic!("unexpected error {err:?}"),
+        }
+
+        for (chunks, want) in [(vec!["partial ", "body"], "partial body"), (vec![], "")] {
+            let chunks = chunks.into_iter().map(Ok).chain([Err(std::io::Error::from(
+                std::io::ErrorKind::ConnectionReset,
+            ))]);
+            let response = Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::wrap_stream(futures_util::stream::iter(chunks)))
+                .unwrap();
+            match fault_from_response(response).await {
+                Error::Fault {
+                    code,
+                    message,
+                    body,
+                } => {
+                    assert_eq!(code, StatusCode::INTERNAL_SERVER_ERROR);
+                    assert_eq!(message, want);
+                    assert_eq!(body, want.as_bytes());
+                }
+                err => panic!("unexpected error {err:?}"),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn limits_response_size() {
+        let response = |chunks: Vec<&'static str>, content_length: Option<usize>| {
+            let mut builder = Response::builder();
+            if let Some(len) = content_length {
+                builder = builder.header(header::CONTENT_LENGTH, len);
+            }
+            let chunks = chunks.into_iter().map(Ok::<_, std::io::Error>);
+            builder
+                .body(Body::wrap_stream(stream::iter(chunks)))
+                .unwrap()
+        };
+
+        let chunks = stream_response_with_limit(response(vec!["abc", "def", "ghi"], None), 8)
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[0].as_ref().unwrap(), "abc");
+        assert_eq!(chunks[1].as_ref().unwrap(), "def");
+        assert!(matches!(
+            chunks[2],
+            Err(Error::ResponseTooLarge { limit: 8 })
+        ));
+
+        let chunks = stream_response_with_limit(response(vec!["abc", "def"], None), 6)
+            .collect::<Vec<_>>()
+            .await;
+        assert!(chunks.iter().all(Result::is_ok));
+
+        let body = get_response_string_with_limit(response(vec!["abc", "def"], None), 6)
+            .await
+            .unwrap();
+        assert_eq!(body, "abcdef");
+        let err = get_response_string_with_limit(response(vec!["abc", "def"], None), 5)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::ResponseTooLarge { limit: 5 }));
+        let err = get_response_string_with_limit(response(vec![], Some(1 << 30)), 1024)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::ResponseTooLarge { limit: 1024 }));
+    }
+}
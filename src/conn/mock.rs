@@ -0,0 +1,366 @@
+//! A programmable transport for testing code using [`RequestClient`](crate::conn::RequestClient)
+//! without a running daemon.
+//!
+//! ```
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() {
+//! use containers_api::conn::{hyper::Method, mock::MockTransport, Error, RequestClient};
+//!
+//! let mock = MockTransport::new();
+//! mock.expect(Method::GET, "/containers/*/json")
+//!     .respond(200, r#"{"Id":"4bd3"}"#);
+//!
+//! let client = RequestClient::<Error>::with_validator(mock.transport(), |resp| {
+//!     Box::pin(async move { Ok(resp) })
+//! });
+//! let container: serde_json::Value = client.get_json("/containers/4bd3/json").await.unwrap();
+//! assert_eq!(container["Id"], "4bd3");
+//! assert_eq!(mock.requests()[0].path, "/containers/4bd3/json");
+//! # }
+//! ```
+
+use crate::conn::{CustomConnector, Transport};
+
+use glob::{MatchOptions, Pattern};
+use hyper::{
+    body::Bytes,
+    header::{HeaderMap, HeaderName, HeaderValue},
+    Body, Method, Request, Response, StatusCode,
+};
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+/// A response returned by a [`MockTransport`] for a matching request.
+#[derive(Debug, Clone)]
+pub struct MockResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    chunks: Vec<Bytes>,
+}
+
+impl MockResponse {
+    /// Creates an empty response with `status`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `status` is not a valid status code.
+    pub fn new(status: u16) -> Self {
+        Self {
+            status: StatusCode::from_u16(status).expect("valid status code"),
+            headers: HeaderMap::new(),
+            chunks: Vec::new(),
+        }
+    }
+
+    /// Adds a header to the response.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `name` or `value` is not a valid header name or value.
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.append(
+            HeaderName::from_bytes(name.as_bytes()).expect("valid header name"),
+            HeaderValue::from_str(value).expect("valid header value"),
+        );
+        self
+    }
+
+    /// Sets the body of the response.
+    pub fn body(mut self, body: impl Into<Bytes>) -> Self {
+        self.chunks = vec![body.into()];
+        self
+    }
+
+    /// Sets the body of the response sent as separate `chunks`, like a streaming endpoint does.
+    pub fn chunks<I, B>(mut self, chunks: I) -> Self
+    where
+        I: IntoIterator<Item = B>,
+        B: Into<Bytes>,
+    {
+        self.chunks = chunks.into_iter().map(Into::into).collect();
+        self
+    }
+
+    fn to_response(&self) -> Response<Body> {
+        let chunks = self
+            .chunks
+            .iter()
+            .cloned()
+            .map(Ok::<_, std::convert::Infallible>)
+            .collect::<Vec<_>>();
+        let mut response = Response::new(Body::wrap_stream(futures_util::stream::iter(chunks)));
+        *response.status_mut() = self.status;
+        *response.headers_mut() = self.headers.clone();
+        response
+    }
+}
+
+/// A request received by a [`MockTransport`].
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub method: Method,
+    pub path: String,
+    pub query: Option<String>,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+type PathMatcher = dyn Fn(&str) -> bool + Send + Sync;
+
+struct Expectation {
+    method: Method,
+    path: Box<PathMatcher>,
+    /// Responses returned in order, the last one is repeated.
+    responses: VecDeque<MockResponse>,
+}
+
+#[derive(Default)]
+struct State {
+    expectations: Vec<Expectation>,
+    requests: Vec<RecordedRequest>,
+}
+
+/// A transport answering requests with programmed responses and recording every request it
+/// receives.
+///
+/// Requests are answered by the first expectation matching their method and path. Requests
+/// without a matching expectation get a `501 Not Implemented` response.
+#[derive(Clone, Default)]
+pub struct MockTransport {
+    state: Arc<Mutex<State>>,
+}
+
+impl fmt::Debug for MockTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.state.lock().unwrap();
+        f.debug_struct("MockTransport")
+            .field("expectations", &state.expectations.len())
+            .field("requests", &state.requests)
+            .finish()
+    }
+}
+
+impl MockTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Expects a request with `method` to a path matching the glob `pattern`, like
+    /// `/containers/*/json`. A `*` doesn't match a `/`, use `**` for that.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `pattern` is not a valid glob pattern.
+    pub fn expect(&self, method: Method, pattern: &str) -> MockExpectation {
+        let pattern = Pattern::new(pattern).expect("valid path pattern");
+        let options = MatchOptions {
+            require_literal_separator: true,
+            ..MatchOptions::new()
+        };
+        self.expect_fn(method, move |path| pattern.matches_with(path, options))
+    }
+
+    /// Expects a request with `method` to a path for which `matches` returns `true`. Use this
+    /// for matching with a regular expression.
+    pub fn expect_fn<F>(&self, method: Method, matches: F) -> MockExpectation
+    where
+        F: Fn(&str) -> bool + Send + Sync + 'static,
+    {
+        let mut state = self.state.lock().unwrap();
+        state.expectations.push(Expectation {
+            method,
+            path: Box::new(matches),
+            responses: VecDeque::new(),
+        });
+        MockExpectation {
+            state: self.state.clone(),
+            index: state.expectations.len() - 1,
+        }
+    }
+
+    /// Returns the requests received so far.
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    /// Creates a transport sending requests to this mock.
+    pub fn transport(&self) -> Transport {
+        let state = self.state.clone();
+        Transport::custom(
+            "mock",
+            CustomConnector::in_memory(move |req| {
+                let state = state.clone();
+                async move { handle(&state, req).await }
+            }),
+        )
+    }
+}
+
+async fn handle(state: &Mutex<State>, req: Request<Body>) -> Response<Body> {
+    let (parts, body) = req.into_parts();
+    let body = hyper::body::to_bytes(body).await.unwrap_or_default();
+    let request = RecordedRequest {
+        method: parts.method,
+        path: parts.uri.path().to_string(),
+        query: parts.uri.query().map(str::to_string),
+        headers: parts.headers,
+        body,
+    };
+
+    let mut state = state.lock().unwrap();
+    let response = state
+        .expectations
+        .iter_mut()
+        .find(|e| e.method == request.method && (e.path)(&request.path))
+        .and_then(|e| {
+            if e.responses.len() > 1 {
+                e.responses.pop_front()
+            } else {
+                e.responses.front().cloned()
+            }
+        })
+        .unwrap_or_else(|| {
+            MockResponse::new(501).body(format!(
+                "no expectation for {} {}",
+                request.method, request.path
+            ))
+        })
+        .to_response();
+    state.requests.push(request);
+    response
+}
+
+/// Handle used to program the responses of an expectation, see [`MockTransport::expect`].
+pub struct MockExpectation {
+    state: Arc<Mutex<State>>,
+    index: usize,
+}
+
+impl MockExpectation {
+    /// Responds with `status` and `body`. Responses are returned in the order they were added,
+    /// the last one is returned for all following requests.
+    pub fn respond(self, status: u16, body: impl Into<Bytes>) -> Self {
+        self.respond_with(MockResponse::new(status).body(body))
+    }
+
+    /// Same as [`respond`](MockExpectation::respond) but with a complete `response`.
+    pub fn respond_with(self, response: MockResponse) -> Self {
+        self.state.lock().unwrap().expectations[self.index]
+            .responses
+            .push_back(response);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MockResponse, MockTransport};
+    use crate::conn::{Error, Headers, Payload, RequestClient};
+    use futures_util::TryStreamExt;
+    use hyper::{header, Body, Method, Response, StatusCode};
+
+    fn client(mock: &MockTransport) -> RequestClient<Error> {
+        RequestClient::with_validator(mock.transport(), |resp: Response<Body>| {
+            Box::pin(async move {
+                let code = resp.status();
+                if code.is_success() {
+                    Ok(resp)
+                } else {
+                    Err(Error::Fault {
+                        code,
+                        message: String::new(),
+                        body: hyper::body::to_bytes(resp.into_body()).await?,
+                    })
+                }
+            })
+        })
+    }
+
+    #[tokio::test]
+    async fn gets_json() {
+        let mock = MockTransport::new();
+        mock.expect(Method::GET, "/containers/json")
+            .respond(200, r#"[{"Id":"a"},{"Id":"b"}]"#);
+        let client = client(&mock);
+
+        let list: Vec<serde_json::Value> =
+            client.get_json("/containers/json?all=true").await.unwrap();
+        assert_eq!(list.len(), 2);
+
+        let err = client.get_json::<()>("/images/json").await.unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Fault { code: StatusCode::NOT_IMPLEMENTED, body, .. } if body == "no expectation for GET /images/json"
+        ));
+
+        let requests = mock.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].method, Method::GET);
+        assert_eq!(requests[0].path, "/containers/json");
+        assert_eq!(requests[0].query.as_deref(), Some("all=true"));
+    }
+
+    #[tokio::test]
+    async fn posts_into_chunked_json_stream() {
+        let mock = MockTransport::new();
+        mock.expect(Method::POST, "/images/create").respond_with(
+            MockResponse::new(200)
+                .header("content-type", "application/json")
+                .chunks([
+                    "{\"status\":\"Pulling\"}\r\n",
+                    "{\"status\":\"Downloading\"}\r\n",
+                    "{\"status\":\"Done\"}\r\n",
+                ]),
+        );
+
+        let messages = client(&mock)
+            .post_into_stream::<_, serde_json::Value>(
+                "/images/create?fromImage=alpine",
+                Payload::Json("{}"),
+                Some(Headers::single("X-Registry-Auth", "token")),
+            )
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[2]["status"], "Done");
+
+        let request = &mock.requests()[0];
+        assert_eq!(request.body, "{}");
+        assert_eq!(request.headers["x-registry-auth"], "token");
+        assert_eq!(request.headers[header::CONTENT_TYPE], "application/json");
+    }
+
+    #[tokio::test]
+    async fn returns_responses_in_sequence() {
+        let mock = MockTransport::new();
+        mock.expect_fn(Method::GET, |path| path.starts_with("/containers/"))
+            .respond(503, "busy")
+            .respond(200, "ok");
+        let client = client(&mock);
+
+        assert!(client.get_string("/containers/a/logs").await.is_err());
+        for _ in 0..2 {
+            assert_eq!(client.get_string("/containers/a/logs").await.unwrap(), "ok");
+        }
+        assert_eq!(mock.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn fails_upgrade_without_switching_protocols() {
+        let mock = MockTransport::new();
+        mock.expect(Method::POST, "/containers/*/attach")
+            .respond_with(MockResponse::new(200).header("content-type", "application/json"));
+
+        let result = client(&mock)
+            .post_upgrade_stream("/containers/a/attach?stream=1", Payload::empty())
+            .await;
+        assert!(matches!(result, Err(Error::ConnectionNotUpgraded)));
+
+        let request = &mock.requests()[0];
+        assert_eq!(request.headers[header::UPGRADE], "tcp");
+    }
+}
@@ -1,22 +1,49 @@
 //! Connection related items
+mod chunks;
 pub mod client;
 mod compat;
+mod connector;
 mod headers;
+mod json;
+pub mod lowlevel;
+#[cfg(feature = "test-util")]
+#[cfg_attr(docsrs, doc(cfg(feature = "test-util")))]
+pub mod mock;
+#[cfg(feature = "opentelemetry")]
+mod otel;
 mod payload;
+mod podman_env;
+pub mod progress;
+mod range;
+mod reconnect;
+#[cfg(test)]
+mod stub;
 pub mod transport;
 pub mod tty;
+mod upgrade;
 
+pub use chunks::{rechunk, Rechunk, StreamStats};
 pub use client::*;
+pub use connector::{CustomConnector, CustomStream};
 pub use headers::Headers;
+pub use json::JsonStream;
 pub use payload::Payload;
+pub use podman_env::{
+    podman_env, PodmanConnection, PodmanEnv, CONTAINERS_CONF, CONTAINER_CONNECTION, CONTAINER_HOST,
+    CONTAINER_SSHKEY,
+};
+pub use range::RangedStream;
+pub use reconnect::ReconnectPolicy;
 pub use transport::*;
 pub use tty::*;
+pub use upgrade::UpgradedStream;
 
 pub(crate) use compat::Compat;
 
 pub use http;
 pub use hyper;
 
+use hyper::body::Bytes;
 use hyper::client::HttpConnector;
 use hyper::StatusCode;
 use serde_json::Error as SerdeError;
@@ -38,15 +65,47 @@ pub type Result<T> = std::result::Result<T, Error>;
 pub enum Error {
     #[error(transparent)]
     SerdeJsonError(#[from] SerdeError),
+    #[error("Failed to deserialize `{type_name}` from response of `{endpoint}` - {source}, near: `{snippet}`")]
+    Deserialize {
+        endpoint: String,
+        type_name: &'static str,
+        /// Part of the response body around the location of the failure.
+        snippet: String,
+        #[source]
+        source: SerdeError,
+    },
     #[error("The HTTP connection was not upgraded by the podman host")]
     ConnectionNotUpgraded,
+    #[error("Connection upgrades are not supported over HTTP/2")]
+    UpgradeNotSupported,
     #[error(transparent)]
     #[allow(clippy::upper_case_acronyms)]
     IO(#[from] futures_util::io::Error),
     #[error("error {code} - {message}")]
-    Fault { code: StatusCode, message: String },
+    Fault {
+        code: StatusCode,
+        message: String,
+        /// Raw body of the response, at most [`MAX_FAULT_BODY_SIZE`] bytes.
+        body: Bytes,
+    },
+    #[error("Failed to connect to the daemon - {0}")]
+    Connect(#[source] hyper::Error),
+    #[error("The request timed out")]
+    Timeout,
+    #[error("The server ignored the requested range")]
+    RangeIgnored,
+    #[error("The response body exceeds the limit of {limit} bytes")]
+    ResponseTooLarge { limit: usize },
+    #[error(transparent)]
+    Progress(#[from] progress::ProgressError),
+    #[error("Invalid TTY frame declaring {declared_len} bytes of data")]
+    InvalidTtyFrame { declared_len: u32 },
+    #[error("Invalid podman connection configuration - {0}")]
+    PodmanConfig(String),
     #[error("Failed to parse uri - {0}")]
     InvalidUri(http::uri::InvalidUri),
+    #[error("Invalid daemon host `{host}` - {reason}")]
+    InvalidHost { host: String, reason: String },
     #[error(transparent)]
     Hyper(#[from] hyper::Error),
     #[error(transparent)]
@@ -60,8 +119,37 @@ pub enum Error {
     Any(Box<dyn std::error::Error + 'static + Send + Sync>),
 }
 
+impl Error {
+    /// Returns the kind of the underlying IO error for [`Error::IO`] and `None` for all other
+    /// variants.
+    pub fn error_kind(&self) -> Option<std::io::ErrorKind> {
+        match self {
+            Error::IO(e) => Some(e.kind()),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if the request failed because of the connection and may succeed when
+    /// retried: connect failures, connections closed or reset while the request was in flight
+    /// and timeouts. Errors returned by the daemon, like [`Error::Fault`], are not transient.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Error::Hyper(e) => e.is_connect() || e.is_closed() || e.is_incomplete_message(),
+            Error::Connect(_) | Error::Timeout => true,
+            Error::IO(e) => matches!(
+                e.kind(),
+                std::io::ErrorKind::ConnectionReset | std::io::ErrorKind::BrokenPipe
+            ),
+            _ => false,
+        }
+    }
+}
+
 pub const AUTH_HEADER: &str = "X-Registry-Auth";
 
+/// Maximum number of bytes of a response body kept in [`Error::Fault`].
+pub const MAX_FAULT_BODY_SIZE: usize = 64 * 1024;
+
 pub fn get_http_connector() -> HttpConnector {
     let mut http = HttpConnector::new();
     http.enforce_http(false);
@@ -87,3 +175,48 @@ pub fn get_https_connector(
 pub fn get_unix_connector() -> hyperlocal::UnixConnector {
     hyperlocal::UnixConnector
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Error;
+    use std::io;
+
+    #[test]
+    fn exposes_io_error_kind() {
+        let err = Error::from(io::Error::from(io::ErrorKind::ConnectionReset));
+        assert_eq!(err.error_kind(), Some(io::ErrorKind::ConnectionReset));
+        assert_eq!(Error::Timeout.error_kind(), None);
+    }
+
+    #[tokio::test]
+    async fn classifies_transient_errors() {
+        use crate::conn::{lowlevel::build_request, Payload, Transport};
+        use hyper::{body::Bytes, Method, StatusCode};
+
+        // nothing listens on port 1
+        let transport = Transport::tcp("127.0.0.1:1").unwrap();
+        let req = build_request(
+            Method::GET,
+            transport.make_uri("/_ping").unwrap(),
+            Payload::empty(),
+            None,
+        )
+        .unwrap();
+        let err = transport.request(req).await.unwrap_err();
+        assert!(err.is_transient(), "{err:?}");
+
+        for kind in [io::ErrorKind::ConnectionReset, io::ErrorKind::BrokenPipe] {
+            assert!(Error::from(io::Error::from(kind)).is_transient());
+        }
+        assert!(Error::Timeout.is_transient());
+
+        assert!(!Error::from(io::Error::from(io::ErrorKind::NotFound)).is_transient());
+        assert!(!Error::Fault {
+            code: StatusCode::SERVICE_UNAVAILABLE,
+            message: "busy".into(),
+            body: Bytes::new(),
+        }
+        .is_transient());
+        assert!(!Error::from(String::from_utf8(vec![0xff]).unwrap_err()).is_transient());
+    }
+}
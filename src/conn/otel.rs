@@ -0,0 +1,70 @@
+//! OpenTelemetry instrumentation of requests sent by [`RequestClient`](crate::conn::RequestClient).
+
+use hyper::{
+    header::{HeaderMap, HeaderName, HeaderValue},
+    Body, Request, StatusCode,
+};
+use opentelemetry::{
+    global::{self, BoxedTracer},
+    propagation::Injector,
+    trace::{SpanKind, Status, TraceContextExt, Tracer},
+    Context, KeyValue,
+};
+use std::fmt;
+
+/// Span covering a single request, from sending it until the response is validated.
+pub(crate) struct RequestSpan {
+    cx: Context,
+}
+
+impl RequestSpan {
+    /// Starts a client span as a child of the current context and injects the context into the
+    /// headers of `req` using the global propagator.
+    pub(crate) fn start(tracer: &BoxedTracer, req: &mut Request<Body>) -> Self {
+        let method = req.method().to_string();
+        let path = req.uri().path().to_string();
+        let span = tracer
+            .span_builder(format!("{method} {path}"))
+            .with_kind(SpanKind::Client)
+            .with_attributes([
+                KeyValue::new("http.request.method", method),
+                KeyValue::new("url.path", path),
+            ])
+            .start_with_context(tracer, &Context::current());
+        let cx = Context::current_with_span(span);
+
+        global::get_text_map_propagator(|propagator| {
+            propagator.inject_context(&cx, &mut HeaderInjector(req.headers_mut()))
+        });
+
+        Self { cx }
+    }
+
+    pub(crate) fn record_status(&self, status: StatusCode) {
+        self.cx.span().set_attribute(KeyValue::new(
+            "http.response.status_code",
+            i64::from(status.as_u16()),
+        ));
+    }
+
+    pub(crate) fn record_error(&self, err: &dyn fmt::Display) {
+        self.cx.span().set_status(Status::error(err.to_string()));
+    }
+
+    pub(crate) fn end(self) {
+        self.cx.span().end();
+    }
+}
+
+struct HeaderInjector<'a>(&'a mut HeaderMap);
+
+impl Injector for HeaderInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(key.as_bytes()),
+            HeaderValue::from_str(&value),
+        ) {
+            self.0.insert(name, value);
+        }
+    }
+}
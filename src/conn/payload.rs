@@ -7,6 +7,15 @@ pub enum Payload<B: Into<Body>> {
     Json(B),
     XTar(B),
     Tar(B),
+    /// A body encoded as `application/x-www-form-urlencoded`, for example with
+    /// [`form_urlencoded`](url::form_urlencoded).
+    Form(B),
+    /// A body streamed from an async source, usually created with
+    /// [`Body::wrap_stream`](hyper::Body::wrap_stream), sent without buffering.
+    BodyStream {
+        stream: Body,
+        mime: Option<mime::Mime>,
+    },
 }
 
 impl Payload<Body> {
@@ -14,17 +23,34 @@ impl Payload<Body> {
     pub fn empty() -> Self {
         Payload::None
     }
+
+    /// Creates a payload streaming `stream` with an optional `mime` type
+    pub fn body_stream(stream: Body, mime: Option<mime::Mime>) -> Self {
+        Payload::BodyStream { stream, mime }
+    }
 }
 
 impl<B: Into<Body>> Payload<B> {
-    /// Extracts the inner body if there is one and returns it
+    /// Extracts the inner body if there is one and returns it. A
+    /// [`BodyStream`](Payload::BodyStream) isn't of type `B` so `None` is returned for it, use
+    /// [`into_body`](Payload::into_body) to get the body of any payload.
     pub fn into_inner(self) -> Option<B> {
+        match self {
+            Self::None | Self::BodyStream { .. } => None,
+            Self::Text(b) | Self::Json(b) | Self::XTar(b) | Self::Tar(b) | Self::Form(b) => Some(b),
+        }
+    }
+
+    /// Converts the payload into the body of a request if there is one
+    pub fn into_body(self) -> Option<Body> {
         match self {
             Self::None => None,
-            Self::Text(b) => Some(b),
-            Self::Json(b) => Some(b),
-            Self::XTar(b) => Some(b),
-            Self::Tar(b) => Some(b),
+            Self::Text(b) => Some(b.into()),
+            Self::Json(b) => Some(b.into()),
+            Self::XTar(b) => Some(b.into()),
+            Self::Tar(b) => Some(b.into()),
+            Self::Form(b) => Some(b.into()),
+            Self::BodyStream { stream, .. } => Some(stream),
         }
     }
 
@@ -36,6 +62,8 @@ impl<B: Into<Body>> Payload<B> {
             Self::Json(_) => Some(mime::APPLICATION_JSON),
             Self::XTar(_) => Some("application/x-tar".parse().expect("parsed mime")),
             Self::Tar(_) => Some("application/tar".parse().expect("parsed mime")),
+            Self::Form(_) => Some(mime::APPLICATION_WWW_FORM_URLENCODED),
+            Self::BodyStream { mime, .. } => mime.clone(),
         }
     }
 
@@ -0,0 +1,426 @@
+//! Resolution of podman remote connections from the environment and `containers.conf`.
+
+use crate::conn::{Error, Result, Transport, TransportBuilder};
+
+use std::{collections::HashMap, path::PathBuf};
+use url::{Position, Url};
+
+/// URI of the service to connect to.
+pub const CONTAINER_HOST: &str = "CONTAINER_HOST";
+/// Path to the ssh key used to connect to the service.
+pub const CONTAINER_SSHKEY: &str = "CONTAINER_SSHKEY";
+/// Name of a connection defined in `containers.conf`.
+pub const CONTAINER_CONNECTION: &str = "CONTAINER_CONNECTION";
+/// Path to the `containers.conf` file, replaces the default location.
+pub const CONTAINERS_CONF: &str = "CONTAINERS_CONF";
+
+const VARS: &[&str] = &[
+    CONTAINER_HOST,
+    CONTAINER_SSHKEY,
+    CONTAINER_CONNECTION,
+    CONTAINERS_CONF,
+    "XDG_CONFIG_HOME",
+    "HOME",
+];
+
+/// A podman service connection resolved by [`PodmanEnv`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PodmanConnection {
+    /// Name of the connection if it was defined in `containers.conf`.
+    pub name: Option<String>,
+    /// URI of the service, like `unix:///run/podman/podman.sock` or
+    /// `ssh://core@localhost:2222/run/podman/podman.sock`.
+    pub uri: String,
+    /// Path to the ssh key used with `ssh://` URIs.
+    pub identity: Option<PathBuf>,
+}
+
+impl PodmanConnection {
+    fn new(name: Option<String>, uri: impl Into<String>) -> Self {
+        Self {
+            name,
+            uri: uri.into(),
+            identity: None,
+        }
+    }
+
+    /// Creates a transport for this connection. Only `unix://` and `tcp://` URIs are supported.
+    pub fn transport(&self) -> Result<Transport> {
+        let url = Url::parse(&self.uri)
+            .map_err(|e| Error::PodmanConfig(format!("invalid uri `{}` - {e}", self.uri)))?;
+        match url.scheme() {
+            #[cfg(unix)]
+            "unix" => Ok(TransportBuilder::unix(url.path()).build()),
+            "tcp" => {
+                let host = format!("http://{}", &url[Position::BeforeHost..Position::AfterPort]);
+                let host = Url::parse(&host).map_err(|e| {
+                    Error::PodmanConfig(format!("invalid uri `{}` - {e}", self.uri))
+                })?;
+                Ok(TransportBuilder::tcp(host).build())
+            }
+            scheme => Err(Error::PodmanConfig(format!(
+                "unsupported uri scheme `{scheme}` of `{}`",
+                self.uri
+            ))),
+        }
+    }
+}
+
+/// Resolves the podman service to connect to the same way `podman --remote` does.
+///
+/// Explicitly set values take precedence over the [`CONTAINER_CONNECTION`], [`CONTAINER_HOST`]
+/// and [`CONTAINER_SSHKEY`] environment variables, which take precedence over the default
+/// connection of `containers.conf`.
+///
+/// Connections defined in `containers.conf` are only available with the `containers-conf`
+/// feature.
+#[derive(Debug, Clone, Default)]
+pub struct PodmanEnv {
+    vars: HashMap<String, String>,
+    config_dir: Option<PathBuf>,
+    connection: Option<String>,
+    uri: Option<String>,
+    identity: Option<PathBuf>,
+}
+
+/// Same as `PodmanEnv::new().resolve()`.
+pub fn podman_env() -> Result<Option<PodmanConnection>> {
+    PodmanEnv::new().resolve()
+}
+
+impl PodmanEnv {
+    /// Creates a resolver reading the environment of the current process.
+    pub fn new() -> Self {
+        Self::from_vars(
+            VARS.iter()
+                .filter_map(|var| std::env::var(var).ok().map(|value| (*var, value))),
+        )
+    }
+
+    /// Creates a resolver reading `vars` instead of the environment of the current process.
+    pub fn from_vars<I, K, V>(vars: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Into<String>,
+    {
+        Self {
+            vars: vars
+                .into_iter()
+                .map(|(k, v)| (k.into(), v.into()))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Sets the directory containing `containers/containers.conf`. Defaults to
+    /// `$XDG_CONFIG_HOME` or `$HOME/.config`. [`CONTAINERS_CONF`] takes precedence over it.
+    pub fn config_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.config_dir = Some(dir.into());
+        self
+    }
+
+    /// Uses the connection named `name` in `containers.conf`.
+    pub fn connection(mut self, name: impl Into<String>) -> Self {
+        self.connection = Some(name.into());
+        self
+    }
+
+    /// Uses the service at `uri`. Takes precedence over [`connection`](PodmanEnv::connection).
+    pub fn uri(mut self, uri: impl Into<String>) -> Self {
+        self.uri = Some(uri.into());
+        self
+    }
+
+    /// Uses the ssh key at `path`.
+    pub fn identity(mut self, path: impl Into<PathBuf>) -> Self {
+        self.identity = Some(path.into());
+        self
+    }
+
+    /// Returns the connection to use or `None` if nothing is configured.
+    pub fn resolve(&self) -> Result<Option<PodmanConnection>> {
+        let connection = if let Some(uri) = &self.uri {
+            Some(PodmanConnection::new(None, uri))
+        } else if let Some(name) = self
+            .connection
+            .as_deref()
+            .or_else(|| self.var(CONTAINER_CONNECTION))
+        {
+            Some(self.named_connection(name)?)
+        } else if let Some(uri) = self.var(CONTAINER_HOST) {
+            Some(PodmanConnection::new(None, uri))
+        } else {
+            self.default_connection()?
+        };
+
+        let identity = self
+            .identity
+            .clone()
+            .or_else(|| self.var(CONTAINER_SSHKEY).map(PathBuf::from));
+        Ok(connection.map(|mut connection| {
+            if identity.is_some() {
+                connection.identity = identity;
+            }
+            connection
+        }))
+    }
+
+    fn var(&self, name: &str) -> Option<&str> {
+        self.vars
+            .get(name)
+            .map(String::as_str)
+            .filter(|v| !v.is_empty())
+    }
+
+    #[cfg_attr(not(feature = "containers-conf"), allow(dead_code))]
+    fn config_path(&self) -> Option<PathBuf> {
+        if let Some(path) = self.var(CONTAINERS_CONF) {
+            return Some(PathBuf::from(path));
+        }
+        let dir = self
+            .config_dir
+            .clone()
+            .or_else(|| self.var("XDG_CONFIG_HOME").map(PathBuf::from))
+            .or_else(|| {
+                self.var("HOME")
+                    .map(|home| PathBuf::from(home).join(".config"))
+            })?;
+        Some(dir.join("containers").join("containers.conf"))
+    }
+
+    #[cfg(feature = "containers-conf")]
+    fn named_connection(&self, name: &str) -> Result<PodmanConnection> {
+        self.load_config()?
+            .engine
+            .service_destinations
+            .remove(name)
+            .map(|dest| dest.into_connection(name.to_string()))
+            .ok_or_else(|| Error::PodmanConfig(format!("unknown connection `{name}`")))
+    }
+
+    #[cfg(not(feature = "containers-conf"))]
+    fn named_connection(&self, name: &str) -> Result<PodmanConnection> {
+        Err(Error::PodmanConfig(format!(
+            "connection `{name}` can't be resolved without the `containers-conf` feature"
+        )))
+    }
+
+    #[cfg(feature = "containers-conf")]
+    fn default_connection(&self) -> Result<Option<PodmanConnection>> {
+        let mut engine = self.load_config()?.engine;
+        let Some(name) = engine.active_service else {
+            return Ok(None);
+        };
+        engine
+            .service_destinations
+            .remove(&name)
+            .map(|dest| Some(dest.into_connection(name.clone())))
+            .ok_or_else(|| Error::PodmanConfig(format!("unknown default connection `{name}`")))
+    }
+
+    #[cfg(not(feature = "containers-conf"))]
+    fn default_connection(&self) -> Result<Option<PodmanConnection>> {
+        Ok(None)
+    }
+
+    #[cfg(feature = "containers-conf")]
+    fn load_config(&self) -> Result<conf::ContainersConf> {
+        let Some(path) = self.config_path() else {
+            return Ok(Default::default());
+        };
+        let data = match std::fs::read_to_string(&path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Default::default()),
+            Err(e) => return Err(e.into()),
+        };
+        toml::from_str(&data)
+            .map_err(|e| Error::PodmanConfig(format!("invalid {} - {e}", path.display())))
+    }
+}
+
+#[cfg(feature = "containers-conf")]
+mod conf {
+    use super::PodmanConnection;
+    use serde::Deserialize;
+    use std::{collections::HashMap, path::PathBuf};
+
+    #[derive(Debug, Default, Deserialize)]
+    pub(super) struct ContainersConf {
+        #[serde(default)]
+        pub engine: EngineConf,
+    }
+
+    #[derive(Debug, Default, Deserialize)]
+    pub(super) struct EngineConf {
+        pub active_service: Option<String>,
+        #[serde(default)]
+        pub service_destinations: HashMap<String, Destination>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub(super) struct Destination {
+        uri: String,
+        identity: Option<PathBuf>,
+    }
+
+    impl Destination {
+        pub(super) fn into_connection(self, name: String) -> PodmanConnection {
+            PodmanConnection {
+                name: Some(name),
+                uri: self.uri,
+                identity: self.identity.filter(|p| !p.as_os_str().is_empty()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PodmanConnection, PodmanEnv, CONTAINER_HOST, CONTAINER_SSHKEY};
+    use crate::conn::{Error, Transport};
+
+    #[test]
+    fn resolves_env() {
+        let env = PodmanEnv::from_vars([
+            (CONTAINER_HOST, "tcp://127.0.0.1:8080"),
+            (CONTAINER_SSHKEY, "/keys/id_ed25519"),
+        ]);
+        let connection = env.resolve().unwrap().unwrap();
+        assert_eq!(
+            connection,
+            PodmanConnection {
+                name: None,
+                uri: "tcp://127.0.0.1:8080".into(),
+                identity: Some("/keys/id_ed25519".into()),
+            }
+        );
+        assert!(matches!(
+            connection.transport().unwrap(),
+            Transport::Tcp { host, .. } if host.as_str() == "http://127.0.0.1:8080/"
+        ));
+
+        let connection = env
+            .uri("unix:///run/podman/podman.sock")
+            .resolve()
+            .unwrap()
+            .unwrap();
+        assert_eq!(connection.uri, "unix:///run/podman/podman.sock");
+        #[cfg(unix)]
+        assert_eq!(
+            connection.transport().unwrap().remote_addr(),
+            "/run/podman/podman.sock"
+        );
+
+        let empty = PodmanEnv::from_vars([("HOME", "/nonexistent")]);
+        assert_eq!(empty.resolve().unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_unsupported_schemes() {
+        let connection = PodmanEnv::from_vars([(CONTAINER_HOST, "ssh://core@localhost:2222")])
+            .resolve()
+            .unwrap()
+            .unwrap();
+        assert!(matches!(
+            connection.transport(),
+            Err(Error::PodmanConfig(msg)) if msg.contains("`ssh`")
+        ));
+    }
+
+    #[cfg(feature = "containers-conf")]
+    fn config_dir(conf: &str) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("containers")).unwrap();
+        std::fs::write(dir.path().join("containers").join("containers.conf"), conf).unwrap();
+        dir
+    }
+
+    #[cfg(feature = "containers-conf")]
+    const CONF: &str = r#"
+[engine]
+active_service = "local"
+
+[engine.service_destinations.local]
+uri = "unix:///run/user/1000/podman/podman.sock"
+
+[engine.service_destinations.remote]
+uri = "ssh://core@10.0.0.2:22/run/podman/podman.sock"
+identity = "/home/core/.ssh/id_rsa"
+"#;
+
+    #[cfg(feature = "containers-conf")]
+    #[test]
+    fn resolves_config_connections() {
+        use super::CONTAINER_CONNECTION;
+        use std::path::PathBuf;
+
+        let dir = config_dir(CONF);
+        let resolve = |vars: &[(&str, &str)]| {
+            PodmanEnv::from_vars(vars.iter().copied())
+                .config_dir(dir.path())
+                .resolve()
+                .unwrap()
+                .unwrap()
+        };
+
+        let default = resolve(&[]);
+        assert_eq!(default.name.as_deref(), Some("local"));
+        assert_eq!(default.uri, "unix:///run/user/1000/podman/podman.sock");
+
+        let remote = resolve(&[(CONTAINER_CONNECTION, "remote")]);
+        assert_eq!(remote.name.as_deref(), Some("remote"));
+        assert_eq!(
+            remote.identity,
+            Some(PathBuf::from("/home/core/.ssh/id_rsa"))
+        );
+
+        let remote = resolve(&[
+            (CONTAINER_CONNECTION, "remote"),
+            (CONTAINER_SSHKEY, "/keys/other"),
+        ]);
+        assert_eq!(remote.identity, Some(PathBuf::from("/keys/other")));
+
+        let host = resolve(&[(CONTAINER_HOST, "tcp://localhost:8888")]);
+        assert_eq!(host.name, None);
+        assert_eq!(host.uri, "tcp://localhost:8888");
+
+        let explicit = PodmanEnv::from_vars([(CONTAINER_CONNECTION, "local")])
+            .config_dir(dir.path())
+            .connection("remote")
+            .resolve()
+            .unwrap()
+            .unwrap();
+        assert_eq!(explicit.name.as_deref(), Some("remote"));
+
+        let err = PodmanEnv::default()
+            .config_dir(dir.path())
+            .connection("missing")
+            .resolve()
+            .unwrap_err();
+        assert!(matches!(err, Error::PodmanConfig(msg) if msg.contains("`missing`")));
+    }
+
+    #[cfg(feature = "containers-conf")]
+    #[test]
+    fn reads_containers_conf_var() {
+        use super::CONTAINERS_CONF;
+
+        let dir = config_dir("[engine]\n");
+        let other = config_dir(CONF);
+        let path = other.path().join("containers").join("containers.conf");
+        let env = PodmanEnv::from_vars([(CONTAINERS_CONF, path.to_str().unwrap())])
+            .config_dir(dir.path());
+        assert_eq!(
+            env.resolve().unwrap().unwrap().name.as_deref(),
+            Some("local")
+        );
+
+        let invalid = config_dir("[engine");
+        let err = PodmanEnv::default()
+            .config_dir(invalid.path())
+            .resolve()
+            .unwrap_err();
+        assert!(matches!(err, Error::PodmanConfig(_)));
+    }
+}
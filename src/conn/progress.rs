@@ -0,0 +1,312 @@
+//! Types for consuming progress messages streamed by build, pull and push endpoints.
+
+use crate::conn::{self, json};
+use futures_util::stream::{self, Stream, StreamExt};
+use hyper::body::Bytes;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::VecDeque;
+
+/// A single message of a progress stream.
+///
+/// Fields that are not known are kept in [`extra`](ProgressMessage::extra).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProgressMessage {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// Output of a build step.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+    /// Human readable progress bar.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub progress: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub progress_detail: Option<ProgressDetail>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_detail: Option<ErrorDetail>,
+    /// Additional data like the id of a built image or the digest of a pushed one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub aux: Option<Value>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl ProgressMessage {
+    /// Returns the error carried by this message if there is one.
+    pub fn error(&self) -> Option<ProgressError> {
+        let detail = self.error_detail.as_ref();
+        let message = self
+            .error
+            .clone()
+            .or_else(|| detail.and_then(|d| d.message.clone()))?;
+        Some(ProgressError {
+            message,
+            code: detail.and_then(|d| d.code),
+        })
+    }
+
+    /// Converts this message into an error if it carries one.
+    pub fn into_result(self) -> Result<Self, ProgressError> {
+        match self.error() {
+            Some(err) => Err(err),
+            None => Ok(self),
+        }
+    }
+
+    /// Deserializes the aux payload as `T`. Returns `None` if there is no aux payload.
+    pub fn aux_as<T: DeserializeOwned>(&self) -> Option<serde_json::Result<T>> {
+        self.aux.clone().map(serde_json::from_value)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ProgressDetail {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub current: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total: Option<u64>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ErrorDetail {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+/// An error reported by the daemon in a progress stream.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("{message}")]
+pub struct ProgressError {
+    pub message: String,
+    pub code: Option<i64>,
+}
+
+/// Name used in place of an endpoint in deserialization errors of a progress stream.
+const PROGRESS_STREAM: &str = "<progress stream>";
+
+/// Adapts a stream of newline delimited JSON chunks, like one returned by
+/// [`RequestClient::post_stream`](crate::conn::RequestClient::post_stream), into a stream of
+/// progress messages. Messages carrying an error are returned as
+/// [`Error::Progress`](crate::conn::Error::Progress).
+///
+/// Messages may be split across chunks in any way.
+pub fn into_progress_stream<'a, S, E>(
+    stream: S,
+) -> impl Stream<Item = Result<ProgressMessage, E>> + 'a
+where
+    S: Stream<Item = Result<Bytes, E>> + 'a,
+    E: From<conn::Error> + 'a,
+{
+    let state = State {
+        stream: Box::pin(stream),
+        buf: Vec::new(),
+        scanned: 0,
+        parsed: VecDeque::new(),
+        done: false,
+    };
+    stream::unfold(state, |mut state| async move {
+        loop {
+            if let Some(item) = state.parsed.pop_front() {
+                return Some((item.map_err(E::from), state));
+            }
+            if let Some(pos) = state.buf[state.scanned..].iter().position(|b| *b == b'\n') {
+                let line = state.buf.drain(..=state.scanned + pos).collect::<Vec<_>>();
+                state.scanned = 0;
+                state.parse(&line);
+                continue;
+            }
+            state.scanned = state.buf.len();
+            if state.done {
+                if state.buf.is_empty() {
+                    return None;
+                }
+                let line = std::mem::take(&mut state.buf);
+                state.scanned = 0;
+                state.parse(&line);
+                continue;
+            }
+            match state.stream.next().await {
+                Some(Ok(chunk)) => state.buf.extend_from_slice(&chunk),
+                Some(Err(e)) => return Some((Err(e), state)),
+                None => state.done = true,
+            }
+        }
+    })
+}
+
+struct State<S> {
+    stream: std::pin::Pin<Box<S>>,
+    buf: Vec<u8>,
+    /// Length of the prefix of `buf` known not to contain a newline.
+    scanned: usize,
+    parsed: VecDeque<conn::Result<ProgressMessage>>,
+    done: bool,
+}
+
+impl<S> State<S> {
+    fn parse(&mut self, line: &[u8]) {
+        let items = serde_json::Deserializer::from_slice(line)
+            .into_iter::<ProgressMessage>()
+            .map(|item| {
+                item.map_err(|e| {
+                    json::deserialize_error::<ProgressMessage>(PROGRESS_STREAM, line, e)
+                })
+                .and_then(|msg| msg.into_result().map_err(conn::Error::from))
+            });
+        self.parsed.extend(items);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{into_progress_stream, ProgressError, ProgressMessage};
+    use crate::conn::Error;
+    use futures_util::{stream, StreamExt};
+    use hyper::body::Bytes;
+    use serde::Deserialize;
+
+    const BUILD_OUTPUT: &str = concat!(
+        r#"{"stream":"Step 1/2 : FROM alpine:3.18"}"#,
+        "\r\n",
+        r#"{"stream":"\n"}"#,
+        "\r\n",
+        r#"{"stream":" ---> 8ca4688f4f35\n"}"#,
+        "\r\n",
+        r#"{"stream":"Step 2/2 : RUN echo hello"}"#,
+        "\r\n",
+        r#"{"aux":{"ID":"sha256:2c4d7b7e1dd1a1b2e4c5d6f7a8b9c0d1e2f3a4b5c6d7e8f9a0b1c2d3e4f5a6b7"}}"#,
+        "\r\n",
+        r#"{"stream":"Successfully built 2c4d7b7e1dd1\n"}"#,
+        "\r\n",
+    );
+
+    const PULL_OUTPUT: &str = concat!(
+        r#"{"status":"Pulling from library/alpine","id":"3.18"}"#,
+        "\r\n",
+        r#"{"status":"Pulling fs layer","progressDetail":{},"id":"c6a83fedfae6"}"#,
+        "\r\n",
+        r#"{"status":"Downloading","progressDetail":{"current":32768,"total":3348259},"progress":"[>      ]  32.77kB/3.348MB","id":"c6a83fedfae6"}"#,
+        "\r\n",
+        r#"{"status":"Digest: sha256:eece025e432126ce23f223450a0326fbebde39cdf496a85d8c016293fc851978"}"#,
+        "\r\n",
+    );
+
+    const BUILD_ERROR: &str = concat!(
+        r#"{"stream":"Step 1/1 : RUN exit 3"}"#,
+        "\n",
+        r#"{"errorDetail":{"code":3,"message":"The command '/bin/sh -c exit 3' returned a non-zero code: 3"},"error":"The command '/bin/sh -c exit 3' returned a non-zero code: 3"}"#,
+        "\n",
+    );
+
+    fn chunked(data: &str, size: usize) -> impl futures_util::Stream<Item = Result<Bytes, Error>> {
+        let chunks = data
+            .as_bytes()
+            .chunks(size)
+            .map(|c| Ok(Bytes::copy_from_slice(c)))
+            .collect::<Vec<_>>();
+        stream::iter(chunks)
+    }
+
+    async fn collect(data: &str, size: usize) -> Vec<Result<ProgressMessage, Error>> {
+        into_progress_stream(chunked(data, size)).collect().await
+    }
+
+    #[tokio::test]
+    async fn parses_build_output() {
+        #[derive(Deserialize)]
+        struct ImageId {
+            #[serde(rename = "ID")]
+            id: String,
+        }
+
+        for size in [1, 7, BUILD_OUTPUT.len()] {
+            let msgs = collect(BUILD_OUTPUT, size)
+                .await
+                .into_iter()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            assert_eq!(msgs.len(), 6);
+            assert_eq!(
+                msgs[0].stream.as_deref(),
+                Some("Step 1/2 : FROM alpine:3.18")
+            );
+            assert_eq!(msgs[2].stream.as_deref(), Some(" ---> 8ca4688f4f35\n"));
+
+            let id = msgs[4].aux_as::<ImageId>().unwrap().unwrap();
+            assert!(id.id.starts_with("sha256:2c4d7b7e1dd1"));
+            assert!(msgs[5].aux_as::<ImageId>().is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn parses_pull_output() {
+        let msgs = collect(PULL_OUTPUT, 13)
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(msgs.len(), 4);
+        assert_eq!(msgs[0].id.as_deref(), Some("3.18"));
+
+        let detail = msgs[1].progress_detail.as_ref().unwrap();
+        assert_eq!((detail.current, detail.total), (None, None));
+
+        let detail = msgs[2].progress_detail.as_ref().unwrap();
+        assert_eq!(detail.current, Some(32768));
+        assert_eq!(detail.total, Some(3348259));
+        assert!(msgs[2].progress.as_deref().unwrap().ends_with("3.348MB"));
+        assert!(msgs[3].status.as_deref().unwrap().starts_with("Digest: "));
+    }
+
+    #[tokio::test]
+    async fn returns_errors() {
+        let msgs = collect(BUILD_ERROR, 10).await;
+        assert_eq!(msgs.len(), 2);
+        assert!(msgs[0].is_ok());
+        match &msgs[1] {
+            Err(Error::Progress(ProgressError { message, code })) => {
+                assert_eq!(*code, Some(3));
+                assert!(message.ends_with("non-zero code: 3"));
+            }
+            other => panic!("unexpected message {other:?}"),
+        }
+
+        let msgs = collect(r#"{"errorDetail":{"message":"denied"}}"#, 5).await;
+        assert!(matches!(
+            &msgs[..],
+            [Err(Error::Progress(ProgressError { message, code: None }))] if message == "denied"
+        ));
+
+        let msgs = collect("{\"status\":\"ok\"}\n{oops}\n", 4).await;
+        assert!(msgs[0].is_ok());
+        assert!(matches!(msgs[1], Err(Error::Deserialize { .. })));
+    }
+
+    #[tokio::test]
+    async fn preserves_unknown_fields() {
+        let data = r#"{"status":"Pushed","id":"abc","platform":{"os":"linux"},"progressDetail":{"current":1,"hidecounts":true}}"#;
+        let msgs = collect(data, 100).await;
+        let msg = msgs[0].as_ref().unwrap();
+        assert_eq!(msg.extra["platform"]["os"], "linux");
+        assert_eq!(
+            msg.progress_detail.as_ref().unwrap().extra["hidecounts"],
+            true
+        );
+        assert!(!msg.extra.contains_key("status"));
+
+        let json = serde_json::to_value(msg).unwrap();
+        assert_eq!(
+            json,
+            serde_json::from_str::<serde_json::Value>(data).unwrap()
+        );
+    }
+}
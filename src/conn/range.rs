@@ -0,0 +1,92 @@
+use futures_util::stream::Stream;
+use hyper::{body::Bytes, header, Body, Response, StatusCode};
+use pin_project::pin_project;
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// Formats the value of a `Range` header requesting bytes from `start` up to and including
+/// `end`, or until the end of the resource if `end` is `None`.
+pub(crate) fn range_header(start: u64, end: Option<u64>) -> String {
+    match end {
+        Some(end) => format!("bytes={start}-{end}"),
+        None => format!("bytes={start}-"),
+    }
+}
+
+/// A stream of byte chunks of a response to a ranged request.
+///
+/// If the server honored the requested range the status is `206 Partial Content` and
+/// [`offset`](RangedStream::offset) returns the position of the first byte of the stream within
+/// the whole resource. If the server ignored the range the status is `200 OK` and the stream
+/// contains the whole resource starting at offset `0`.
+#[pin_project]
+#[derive(Debug)]
+pub struct RangedStream<S> {
+    status: StatusCode,
+    content_range: Option<String>,
+    #[pin]
+    stream: S,
+}
+
+impl<S> RangedStream<S> {
+    pub(crate) fn new(
+        response: Response<Body>,
+        stream_fn: impl FnOnce(Response<Body>) -> S,
+    ) -> Self {
+        Self {
+            status: response.status(),
+            content_range: response
+                .headers()
+                .get(header::CONTENT_RANGE)
+                .and_then(|v| v.to_str().ok())
+                .map(String::from),
+            stream: stream_fn(response),
+        }
+    }
+
+    /// Status of the response.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// Raw value of the `Content-Range` header of the response if there was one.
+    pub fn content_range(&self) -> Option<&str> {
+        self.content_range.as_deref()
+    }
+
+    /// Returns true if the server responded with only a part of the resource.
+    pub fn is_partial(&self) -> bool {
+        self.status == StatusCode::PARTIAL_CONTENT
+    }
+
+    /// Position of the first byte of this stream within the whole resource.
+    pub fn offset(&self) -> u64 {
+        if !self.is_partial() {
+            return 0;
+        }
+        self.content_range
+            .as_deref()
+            .and_then(|range| range.strip_prefix("bytes "))
+            .and_then(|range| range.split('-').next())
+            .and_then(|start| start.trim().parse().ok())
+            .unwrap_or_default()
+    }
+
+    /// Returns the underlying stream of byte chunks.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+impl<S, E> Stream for RangedStream<S>
+where
+    S: Stream<Item = Result<Bytes, E>>,
+{
+    type Item = Result<Bytes, E>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.project().stream.poll_next(cx)
+    }
+}
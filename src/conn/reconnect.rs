@@ -0,0 +1,94 @@
+use std::time::Duration;
+
+/// Describes how a resumable stream reconnects after the connection drops.
+///
+/// The delay between attempts starts at `initial_backoff` and doubles with every consecutive
+/// failed attempt up to `max_backoff`. The attempt counter resets once an item is received.
+#[derive(Debug, Clone)]
+pub struct ReconnectPolicy {
+    max_retries: Option<usize>,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: None,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// Creates a policy that reconnects indefinitely with the default backoff.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a policy that never reconnects.
+    pub fn never() -> Self {
+        Self::default().max_retries(0)
+    }
+
+    /// Maximum number of consecutive reconnection attempts before giving up.
+    pub fn max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = Some(max_retries);
+        self
+    }
+
+    /// Delay before the first reconnection attempt.
+    pub fn initial_backoff(mut self, backoff: Duration) -> Self {
+        self.initial_backoff = backoff;
+        self
+    }
+
+    /// Upper bound of the delay between reconnection attempts.
+    pub fn max_backoff(mut self, backoff: Duration) -> Self {
+        self.max_backoff = backoff;
+        self
+    }
+
+    /// Returns true if another reconnection attempt is allowed after `attempt` consecutive
+    /// attempts.
+    pub(crate) fn allows(&self, attempt: usize) -> bool {
+        self.max_retries.map(|max| attempt <= max).unwrap_or(true)
+    }
+
+    /// Returns the delay before the `attempt`-th consecutive reconnection attempt.
+    pub(crate) fn backoff(&self, attempt: usize) -> Duration {
+        let exp = attempt.saturating_sub(1).min(31) as u32;
+        self.initial_backoff
+            .saturating_mul(2u32.saturating_pow(exp))
+            .min(self.max_backoff)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ReconnectPolicy;
+    use std::time::Duration;
+
+    #[test]
+    fn backoff_doubles_up_to_max() {
+        let policy = ReconnectPolicy::new()
+            .initial_backoff(Duration::from_millis(100))
+            .max_backoff(Duration::from_millis(500));
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2), Duration::from_millis(200));
+        assert_eq!(policy.backoff(3), Duration::from_millis(400));
+        assert_eq!(policy.backoff(4), Duration::from_millis(500));
+        assert_eq!(policy.backoff(100), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn respects_max_retries() {
+        let policy = ReconnectPolicy::new().max_retries(2);
+        assert!(policy.allows(1));
+        assert!(policy.allows(2));
+        assert!(!policy.allows(3));
+        assert!(!ReconnectPolicy::never().allows(1));
+        assert!(ReconnectPolicy::new().allows(usize::MAX));
+    }
+}
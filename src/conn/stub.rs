@@ -0,0 +1,83 @@
+//! Helpers for running requests against a local stub server in tests.
+
+use crate::conn::{
+    get_http_connector, lowlevel::fault_from_response, Error, RequestClient, Transport,
+};
+
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, Client, Request, Response, Server,
+};
+use std::{convert::Infallible, future::Future, net::SocketAddr, pin::Pin};
+
+/// Spawns a server on a random local port that answers every request with `handler`.
+pub(crate) fn serve<F, Fut>(handler: F) -> SocketAddr
+where
+    F: Fn(Request<Body>) -> Fut + Clone + Send + Sync + 'static,
+    Fut: Future<Output = Response<Body>> + Send + 'static,
+{
+    serve_with(handler, false)
+}
+
+/// Same as [`serve`] but the server only speaks HTTP/2.
+pub(crate) fn serve_h2<F, Fut>(handler: F) -> SocketAddr
+where
+    F: Fn(Request<Body>) -> Fut + Clone + Send + Sync + 'static,
+    Fut: Future<Output = Response<Body>> + Send + 'static,
+{
+    serve_with(handler, true)
+}
+
+fn serve_with<F, Fut>(handler: F, http2_only: bool) -> SocketAddr
+where
+    F: Fn(Request<Body>) -> Fut + Clone + Send + Sync + 'static,
+    Fut: Future<Output = Response<Body>> + Send + 'static,
+{
+    let make_svc = make_service_fn(move |_| {
+        let handler = handler.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                let fut = handler(req);
+                async move { Ok::<_, Infallible>(fut.await) }
+            }))
+        }
+    });
+    let server = Server::bind(&([127, 0, 0, 1], 0).into())
+        .http2_only(http2_only)
+        .serve(make_svc);
+    let addr = server.local_addr();
+    tokio::spawn(server);
+    addr
+}
+
+/// Creates a TCP transport pointing at `addr`.
+pub(crate) fn transport(addr: SocketAddr) -> Transport {
+    Transport::Tcp {
+        client: Client::builder().build(get_http_connector()),
+        host: format!("http://{addr}").parse().unwrap(),
+    }
+}
+
+fn validate(
+    response: Response<Body>,
+) -> Pin<Box<dyn Future<Output = Result<Response<Body>, Error>> + Send + Sync>> {
+    Box::pin(async move {
+        let status = response.status();
+        if status.is_success() || status.is_informational() {
+            return Ok(response);
+        }
+        Err(fault_from_response(response).await)
+    })
+}
+
+/// Creates a client pointing at `addr` that turns unsuccessful responses into
+/// [`Error::Fault`].
+pub(crate) fn client(addr: SocketAddr) -> RequestClient<Error> {
+    RequestClient::new(transport(addr), Box::new(validate))
+}
+
+/// Same as [`client`] but talks HTTP/2 with prior knowledge.
+pub(crate) fn client_h2(addr: SocketAddr) -> RequestClient<Error> {
+    let host = format!("http://{addr}").parse().unwrap();
+    RequestClient::new(Transport::tcp_h2(host), Box::new(validate))
+}
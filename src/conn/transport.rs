@@ -1,13 +1,15 @@
 //! Transports for communicating with the Podman or Docker daemon
 
-use crate::conn::{Error, Headers, Payload, Result};
-
-use futures_util::{
-    stream::{self, Stream},
-    StreamExt,
+#[cfg(unix)]
+use crate::conn::get_unix_connector;
+use crate::conn::{
+    get_http_connector,
+    lowlevel::{build_request, get_response_string},
+    CustomConnector, Error, Headers, Payload, Result,
 };
+
 use hyper::{
-    body::Bytes,
+    body::{Bytes, HttpBody},
     client::{Client, HttpConnector},
     header, Body, Method, Request, Response,
 };
@@ -17,9 +19,74 @@ use hyper_openssl::HttpsConnector;
 use hyperlocal::UnixConnector;
 #[cfg(unix)]
 use hyperlocal::Uri as DomainUri;
+use tokio::io::{AsyncRead, AsyncWrite};
 use url::Url;
 
-use std::{iter::IntoIterator, path::PathBuf};
+use std::{
+    fmt,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+/// Endpoint used by Docker to check whether the daemon is reachable. Podman uses
+/// `/libpod/_ping`.
+pub const PING_ENDPOINT: &str = "/_ping";
+
+/// Time after which a ping is considered failed regardless of the client configuration.
+const PING_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Parses the `host` of a TCP transport appending `base_path` to its path, which is stored
+/// without a trailing slash.
+fn parse_host(host: &str, base_path: &str) -> Result<Url> {
+    let invalid = |reason: &str| Error::InvalidHost {
+        host: host.to_string(),
+        reason: reason.to_string(),
+    };
+
+    let mut url = if host.contains("://") {
+        Url::parse(host)
+    } else {
+        Url::parse(&format!("http://{host}"))
+    }
+    .map_err(|e| invalid(&e.to_string()))?;
+
+    match url.scheme() {
+        "http" => {}
+        "tcp" => {
+            url = Url::parse(&format!("http{}", &url.as_str()["tcp".len()..]))
+                .map_err(|e| invalid(&e.to_string()))?
+        }
+        scheme => return Err(invalid(&format!("unsupported scheme `{scheme}`"))),
+    }
+    if url.host_str().map(str::is_empty).unwrap_or(true) {
+        return Err(invalid("missing host"));
+    }
+    if url.query().is_some() {
+        return Err(invalid("query is not allowed"));
+    }
+    if url.fragment().is_some() {
+        return Err(invalid("fragment is not allowed"));
+    }
+
+    let path = [url.path(), base_path]
+        .iter()
+        .flat_map(|p| p.split('/'))
+        .filter(|segment| !segment.is_empty())
+        .fold(String::new(), |path, segment| path + "/" + segment);
+    url.set_path(&path);
+    Ok(url)
+}
+
+/// Joins the endpoint `ep` to `host` with exactly one `/` between the path of `host` and `ep`.
+fn join_uri(host: &Url, ep: &str) -> Result<hyper::Uri> {
+    format!(
+        "{}/{}",
+        host.as_str().trim_end_matches('/'),
+        ep.trim_start_matches('/')
+    )
+    .parse()
+    .map_err(Error::InvalidUri)
+}
 
 /// Transports are types which define supported means of communication.
 #[derive(Clone, Debug)]
@@ -29,6 +96,12 @@ pub enum Transport {
         client: Client<HttpConnector>,
         host: Url,
     },
+    /// A network tcp interface speaking HTTP/2 with prior knowledge (h2c). Connection
+    /// upgrades are not available on this transport.
+    Http2Tcp {
+        client: Client<HttpConnector>,
+        host: Url,
+    },
     /// TCP/TLS
     #[cfg(feature = "tls")]
     #[cfg_attr(docsrs, doc(cfg(feature = "tls")))]
@@ -42,28 +115,92 @@ pub enum Transport {
         client: Client<UnixConnector>,
         path: PathBuf,
     },
+    /// A connection established by a caller supplied connector, see
+    /// [`Transport::custom`] and [`Transport::from_stream`].
+    Custom {
+        client: Client<CustomConnector>,
+        label: String,
+    },
 }
 
 impl Transport {
+    /// Creates a TCP transport that talks to `host`, like `tcp://localhost:2375` or
+    /// `http://10.0.0.2:2375`. The scheme defaults to `http` if missing, `tcp` is the same as
+    /// `http`.
+    ///
+    /// A path of `host` is kept as a base path prefixed to every endpoint, for a daemon behind
+    /// a reverse proxy at `http://proxy/docker`. Fails with [`Error::InvalidHost`] if `host`
+    /// isn't a valid URL, has a query or a fragment or uses a scheme other than `http`.
+    pub fn tcp(host: impl AsRef<str>) -> Result<Self> {
+        Self::tcp_with_base_path(host, "")
+    }
+
+    /// Same as [`tcp`](Transport::tcp) but endpoints are prefixed with `base_path` appended to
+    /// the path of `host`.
+    pub fn tcp_with_base_path(host: impl AsRef<str>, base_path: impl AsRef<str>) -> Result<Self> {
+        Ok(Self::Tcp {
+            client: Client::builder().build(get_http_connector()),
+            host: parse_host(host.as_ref(), base_path.as_ref())?,
+        })
+    }
+
+    /// Creates a TCP transport that talks to `host` using HTTP/2 without negotiation. Useful
+    /// when the daemon sits behind a proxy that only accepts h2c.
+    pub fn tcp_h2(host: Url) -> Self {
+        Self::Http2Tcp {
+            client: Client::builder()
+                .http2_only(true)
+                .build(get_http_connector()),
+            host,
+        }
+    }
+
+    /// Creates a transport establishing connections with `connector`. `label` is returned from
+    /// [`remote_addr`](Transport::remote_addr).
+    pub fn custom(label: impl Into<String>, connector: CustomConnector) -> Self {
+        Self::Custom {
+            client: Client::builder().build(connector),
+            label: label.into(),
+        }
+    }
+
+    /// Creates a transport sending requests over an already connected `stream`, like a socket
+    /// passed by systemd socket activation. See [`CustomConnector::from_stream`].
+    pub fn from_stream<S>(label: impl Into<String>, stream: S) -> Self
+    where
+        S: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        Self::custom(label, CustomConnector::from_stream(stream))
+    }
+
+    /// Returns `true` if requests sent through this transport use HTTP/2.
+    pub fn is_http2(&self) -> bool {
+        matches!(self, Self::Http2Tcp { .. })
+    }
+
     pub fn remote_addr(&self) -> &str {
         match &self {
             Self::Tcp { ref host, .. } => host.as_ref(),
+            Self::Http2Tcp { ref host, .. } => host.as_ref(),
             #[cfg(feature = "tls")]
             Self::EncryptedTcp { ref host, .. } => host.as_ref(),
             #[cfg(unix)]
             Self::Unix { ref path, .. } => path.to_str().unwrap_or_default(),
+            Self::Custom { ref label, .. } => label,
         }
     }
 
     pub fn make_uri(&self, ep: &str) -> Result<hyper::Uri> {
         match self {
-            Transport::Tcp { host, .. } => format!("{host}{ep}").parse().map_err(Error::InvalidUri),
+            Transport::Tcp { host, .. } | Transport::Http2Tcp { host, .. } => join_uri(host, ep),
             #[cfg(feature = "tls")]
-            Transport::EncryptedTcp { host, .. } => {
-                format!("{host}{ep}").parse().map_err(Error::InvalidUri)
-            }
+            Transport::EncryptedTcp { host, .. } => join_uri(host, ep),
             #[cfg(unix)]
             Transport::Unix { path, .. } => Ok(DomainUri::new(path, ep).into()),
+            // the connector ignores the uri, the host is only there to make it valid
+            Transport::Custom { .. } => format!("http://localhost/{}", ep.trim_start_matches('/'))
+                .parse()
+                .map_err(Error::InvalidUri),
         }
     }
 
@@ -71,109 +208,433 @@ impl Transport {
     pub async fn request(&self, req: Request<Body>) -> Result<Response<Body>> {
         log::trace!("sending request {} {}", req.method(), req.uri());
         match self {
-            Transport::Tcp { ref client, .. } => client.request(req),
+            Transport::Tcp { ref client, .. } | Transport::Http2Tcp { ref client, .. } => {
+                client.request(req)
+            }
             #[cfg(feature = "tls")]
             Transport::EncryptedTcp { ref client, .. } => client.request(req),
             #[cfg(unix)]
             Transport::Unix { ref client, .. } => client.request(req),
+            Transport::Custom { ref client, .. } => client.request(req),
         }
         .await
         .map_err(Error::from)
     }
 
+    /// Send the given request and return the headers and the body of the response separately, so
+    /// that the headers can be inspected before deciding how to consume the body.
+    ///
+    /// The response is not validated, [`RequestClient`](crate::conn::RequestClient) passes the
+    /// whole response to its validator first and splits it afterwards.
+    pub async fn request_split(&self, req: Request<Body>) -> Result<(header::HeaderMap, Body)> {
+        let (parts, body) = self.request(req).await?.into_parts();
+        Ok((parts.headers, body))
+    }
+
+    /// Checks whether the daemon is reachable by sending a `HEAD` request to
+    /// [`PING_ENDPOINT`] and returns the round-trip latency.
+    pub async fn ping(&self) -> Result<Duration> {
+        self.ping_endpoint(PING_ENDPOINT).await
+    }
+
+    /// Same as [`ping`](Transport::ping) but sends the request to `ep`.
+    ///
+    /// Returns [`Error::Connect`] if the daemon couldn't be reached, [`Error::Fault`] if it
+    /// responded with an unsuccessful status and [`Error::Timeout`] if it didn't respond in time.
+    pub async fn ping_endpoint(&self, ep: &str) -> Result<Duration> {
+        self.ping_with_timeout(ep, PING_TIMEOUT).await
+    }
+
+    pub(crate) async fn ping_with_timeout(&self, ep: &str, timeout: Duration) -> Result<Duration> {
+        let req = build_request(
+            Method::HEAD,
+            self.make_uri(ep)?,
+            Payload::empty(),
+            Headers::none(),
+        )?;
+
+        let start = Instant::now();
+        let response = match tokio::time::timeout(timeout, self.request(req)).await {
+            Ok(Ok(response)) => response,
+            Ok(Err(Error::Hyper(e))) if e.is_connect() => return Err(Error::Connect(e)),
+            Ok(Err(e)) => return Err(e),
+            Err(_) => return Err(Error::Timeout),
+        };
+        let latency = start.elapsed();
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(Error::Fault {
+                code: status,
+                message: status.canonical_reason().unwrap_or_default().to_string(),
+                body: Bytes::new(),
+            });
+        }
+
+        Ok(latency)
+    }
+
     pub async fn request_string(&self, req: Request<Body>) -> Result<String> {
-        let body = self.request(req).await.map(|resp| resp.into_body())?;
-        body_to_string(body).await
+        get_response_string(self.request(req).await?).await
     }
 }
 
-pub(crate) async fn body_to_string(body: Body) -> Result<String> {
-    let bytes = hyper::body::to_bytes(body).await?;
-    String::from_utf8(bytes.to_vec()).map_err(Error::from)
+#[derive(Clone)]
+enum TransportKind {
+    Tcp(Url),
+    #[cfg(feature = "tls")]
+    EncryptedTcp(Url, HttpsConnector<HttpConnector>),
+    #[cfg(unix)]
+    Unix(PathBuf),
 }
 
-/// Builds an HTTP request.
-pub(crate) fn build_request<B>(
-    method: Method,
-    uri: hyper::Uri,
-    body: Payload<B>,
-    headers: Option<Headers>,
-) -> Result<Request<Body>>
-where
-    B: Into<Body>,
-{
-    let builder = hyper::http::request::Builder::new();
-    let req = builder.method(method).uri(&uri);
-    let mut req = req.header(header::HOST, "");
-
-    if let Some(h) = headers {
-        for (k, v) in h.into_iter() {
-            req = req.header(k, v);
+impl fmt::Debug for TransportKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp(host) => f.debug_tuple("Tcp").field(host).finish(),
+            #[cfg(feature = "tls")]
+            Self::EncryptedTcp(host, _) => f.debug_tuple("EncryptedTcp").field(host).finish(),
+            #[cfg(unix)]
+            Self::Unix(path) => f.debug_tuple("Unix").field(path).finish(),
         }
     }
+}
+
+/// Builder for a [`Transport`] with a configured connection pool.
+///
+/// Transports created directly use the defaults of [`hyper::Client`].
+#[derive(Clone, Debug)]
+pub struct TransportBuilder {
+    kind: TransportKind,
+    client: hyper::client::Builder,
+}
 
-    // early return
-    if body.is_none() {
-        return Ok(req.body(Body::empty())?);
+impl TransportBuilder {
+    fn new(kind: TransportKind) -> Self {
+        Self {
+            kind,
+            client: Client::builder(),
+        }
     }
 
-    let mime = body.mime_type();
-    if let Some(c) = mime {
-        req = req.header(header::CONTENT_TYPE, &c.to_string());
+    /// Builds a [`Transport::Tcp`] connecting to `host`.
+    pub fn tcp(host: Url) -> Self {
+        Self::new(TransportKind::Tcp(host))
     }
 
-    // it's ok to unwrap, we check that the body is not none
-    req.body(body.into_inner().unwrap().into())
-        .map_err(Error::from)
-}
+    #[cfg(feature = "tls")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "tls")))]
+    /// Builds a [`Transport::EncryptedTcp`] connecting to `host` with `connector`, usually
+    /// created with [`get_https_connector`](crate::conn::get_https_connector).
+    pub fn encrypted_tcp(host: Url, connector: HttpsConnector<HttpConnector>) -> Self {
+        Self::new(TransportKind::EncryptedTcp(host, connector))
+    }
 
-pub(crate) async fn get_response_string(response: Response<Body>) -> Result<String> {
-    body_to_string(response.into_body()).await
-}
+    #[cfg(unix)]
+    /// Builds a [`Transport::Unix`] connecting to the socket at `path`.
+    pub fn unix(path: impl Into<PathBuf>) -> Self {
+        Self::new(TransportKind::Unix(path.into()))
+    }
+
+    /// Sets the maximum number of idle connections kept open for each host.
+    pub fn pool_max_idle_per_host(mut self, max_idle: usize) -> Self {
+        self.client.pool_max_idle_per_host(max_idle);
+        self
+    }
+
+    /// Sets how long idle connections are kept open. `None` keeps them open until the daemon
+    /// closes them.
+    pub fn pool_idle_timeout(mut self, timeout: impl Into<Option<Duration>>) -> Self {
+        self.client.pool_idle_timeout(timeout);
+        self
+    }
 
-pub(crate) fn stream_response(response: Response<Body>) -> impl Stream<Item = Result<Bytes>> {
-    stream_body(response.into_body())
+    pub fn build(self) -> Transport {
+        match self.kind {
+            TransportKind::Tcp(host) => Transport::Tcp {
+                client: self.client.build(get_http_connector()),
+                host,
+            },
+            #[cfg(feature = "tls")]
+            TransportKind::EncryptedTcp(host, connector) => Transport::EncryptedTcp {
+                client: self.client.build(connector),
+                host,
+            },
+            #[cfg(unix)]
+            TransportKind::Unix(path) => Transport::Unix {
+                client: self.client.build(get_unix_connector()),
+                path,
+            },
+        }
+    }
 }
 
-pub(crate) fn stream_json_response(response: Response<Body>) -> impl Stream<Item = Result<Bytes>> {
-    stream_json_body(response.into_body())
+/// Reads the remaining data of `body`, discarding it, and returns the trailers sent after it,
+/// if any. Use this with a response returned by
+/// [`RequestClient::get_raw`](crate::conn::RequestClient::get_raw) after consuming as much of
+/// the body as needed.
+pub async fn read_trailers(body: &mut Body) -> Result<Option<header::HeaderMap>> {
+    while let Some(chunk) = body.data().await {
+        chunk?;
+    }
+    body.trailers().await.map_err(Error::from)
 }
 
-fn stream_body(body: Body) -> impl Stream<Item = Result<Bytes>> {
-    async fn unfold(mut body: Body) -> Option<(Result<Bytes>, Body)> {
-        body.next()
-            .await
-            .map(|chunk| (chunk.map_err(Error::from), body))
+#[cfg(test)]
+mod tests {
+    use crate::conn::{
+        lowlevel::build_request, stub, Error, Headers, Payload, Transport, TransportBuilder,
+    };
+    use hyper::{header, Body, Method, Response, StatusCode};
+    use std::time::Duration;
+
+    #[test]
+    fn builds_validated_tcp_transports() {
+        let uri = |transport: Result<Transport, Error>, ep: &str| {
+            transport.unwrap().make_uri(ep).unwrap().to_string()
+        };
+
+        let cases = [
+            ("localhost:2375", "", "/info", "http://localhost:2375/info"),
+            ("localhost", "", "info", "http://localhost/info"),
+            (
+                "tcp://10.0.0.2:2375",
+                "",
+                "/info",
+                "http://10.0.0.2:2375/info",
+            ),
+            (
+                "http://10.0.0.2:2375/",
+                "",
+                "/info",
+                "http://10.0.0.2:2375/info",
+            ),
+            (
+                "http://proxy/docker",
+                "",
+                "/info",
+                "http://proxy/docker/info",
+            ),
+            (
+                "http://proxy/docker//",
+                "",
+                "info",
+                "http://proxy/docker/info",
+            ),
+            (
+                "http://proxy:8080",
+                "/docker/",
+                "/v1.41/info?x=1",
+                "http://proxy:8080/docker/v1.41/info?x=1",
+            ),
+            (
+                "http://proxy/api/",
+                "docker",
+                "/containers/json",
+                "http://proxy/api/docker/containers/json",
+            ),
+        ];
+        for (host, base_path, ep, want) in cases {
+            let transport = Transport::tcp_with_base_path(host, base_path);
+            assert_eq!(uri(transport, ep), want, "{host} {base_path}");
+        }
+        assert_eq!(
+            uri(Transport::tcp("http://proxy/docker/"), "/_ping"),
+            "http://proxy/docker/_ping"
+        );
+
+        for host in [
+            "http://localhost:2375/?all=1",
+            "http://localhost:2375/#top",
+            "https://localhost:2376",
+            "unix:///var/run/docker.sock",
+            "http://",
+            "localhost:port",
+        ] {
+            let err = Transport::tcp(host).unwrap_err();
+            assert!(
+                matches!(&err, Error::InvalidHost { host: h, .. } if h == host),
+                "{host}: {err:?}"
+            );
+        }
     }
 
-    stream::unfold(body, unfold)
-}
+    #[tokio::test]
+    async fn builds_pooled_transports() {
+        let addr =
+            stub::serve(
+                |req| async move { Response::new(Body::from(req.uri().path().to_string())) },
+            );
 
-static JSON_WHITESPACE: &[u8] = b"\r\n";
-
-fn stream_json_body(body: Body) -> impl Stream<Item = Result<Bytes>> {
-    async fn unfold(mut body: Body) -> Option<(Result<Bytes>, Body)> {
-        let mut chunk = Vec::new();
-        while let Some(chnk) = body.next().await {
-            match chnk {
-                Ok(chnk) => {
-                    chunk.extend(chnk.to_vec());
-                    if chnk.ends_with(JSON_WHITESPACE) {
-                        break;
-                    }
-                }
-                Err(e) => {
-                    return Some((Err(Error::from(e)), body));
-                }
-            }
+        let transport = TransportBuilder::tcp(format!("http://{addr}").parse().unwrap())
+            .pool_max_idle_per_host(64)
+            .pool_idle_timeout(Duration::from_secs(300))
+            .build();
+        assert!(matches!(transport, Transport::Tcp { .. }));
+        for _ in 0..3 {
+            let req = build_request(
+                Method::GET,
+                transport.make_uri("/info").unwrap(),
+                Payload::empty(),
+                Headers::none(),
+            )
+            .unwrap();
+            assert!(transport
+                .request_string(req)
+                .await
+                .unwrap()
+                .ends_with("/info"));
+        }
+
+        #[cfg(unix)]
+        {
+            let transport = TransportBuilder::unix("/var/run/docker.sock")
+                .pool_idle_timeout(None)
+                .build();
+            assert_eq!(transport.remote_addr(), "/var/run/docker.sock");
         }
+    }
+
+    #[tokio::test]
+    async fn splits_response() {
+        let addr = stub::serve(|_| async {
+            Response::builder()
+                .header(header::CONTENT_TYPE, "application/x-tar")
+                .body(Body::from("archive"))
+                .unwrap()
+        });
+
+        let transport = stub::transport(addr);
+        let req = build_request(
+            Method::GET,
+            transport.make_uri("/containers/test/archive").unwrap(),
+            Payload::empty(),
+            Headers::none(),
+        )
+        .unwrap();
+        let (headers, body) = transport.request_split(req).await.unwrap();
+        assert_eq!(headers[header::CONTENT_TYPE], "application/x-tar");
+        assert_eq!(hyper::body::to_bytes(body).await.unwrap(), "archive");
+    }
+
+    #[tokio::test]
+    async fn requests_over_pre_connected_stream() {
+        use hyper::{server::conn::Http, service::service_fn};
+
+        let (client, server) = tokio::io::duplex(1024);
+        tokio::spawn(Http::new().serve_connection(
+            server,
+            service_fn(|req: hyper::Request<Body>| async move {
+                let body = format!("{} {}", req.method(), req.uri().path());
+                Ok::<_, std::convert::Infallible>(Response::new(Body::from(body)))
+            }),
+        ));
+
+        let transport = Transport::from_stream("socket-activated", client);
+        assert_eq!(transport.remote_addr(), "socket-activated");
 
-        if chunk.is_empty() {
-            return None;
+        for ep in ["/info", "/version"] {
+            let req = build_request(
+                Method::GET,
+                transport.make_uri(ep).unwrap(),
+                Payload::empty(),
+                Headers::none(),
+            )
+            .unwrap();
+            let body = transport.request_string(req).await.unwrap();
+            assert_eq!(body, format!("GET {ep}"));
         }
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn requests_in_memory() {
+        use crate::conn::CustomConnector;
+
+        let transport = Transport::custom(
+            "memory",
+            CustomConnector::in_memory(|req| async move {
+                Response::new(Body::from(req.uri().path().to_string()))
+            }),
+        );
 
-        Some((Ok(Bytes::from(chunk)), body))
+        let (first, second) = futures_util::join!(
+            transport.request_string(
+                build_request(
+                    Method::GET,
+                    transport.make_uri("/info").unwrap(),
+                    Payload::empty(),
+                    Headers::none(),
+                )
+                .unwrap()
+            ),
+            transport.request_string(
+                build_request(
+                    Method::GET,
+                    transport.make_uri("containers/json").unwrap(),
+                    Payload::empty(),
+                    Headers::none(),
+                )
+                .unwrap()
+            ),
+        );
+        assert_eq!(first.unwrap(), "/info");
+        assert_eq!(second.unwrap(), "/containers/json");
     }
 
-    stream::unfold(body, unfold)
+    #[tokio::test]
+    async fn pings_daemon() {
+        let addr = stub::serve(|req| async move {
+            assert_eq!(req.method(), Method::HEAD);
+            assert!(req.uri().path().ends_with("/libpod/_ping"));
+            Response::new(Body::empty())
+        });
+
+        let transport = stub::transport(addr);
+        transport.ping_endpoint("/libpod/_ping").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn ping_reports_http_failure() {
+        let addr = stub::serve(|_| async {
+            Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::empty())
+                .unwrap()
+        });
+
+        let err = stub::transport(addr).ping().await.unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Fault {
+                code: StatusCode::INTERNAL_SERVER_ERROR,
+                ..
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn ping_reports_connect_failure() {
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        let err = stub::transport(addr).ping().await.unwrap_err();
+        assert!(matches!(err, Error::Connect(_)), "{err:?}");
+    }
+
+    #[tokio::test]
+    async fn ping_times_out() {
+        let addr = stub::serve(|_| async {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            Response::new(Body::empty())
+        });
+
+        let err = stub::transport(addr)
+            .ping_with_timeout("/_ping", Duration::from_millis(50))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::Timeout));
+    }
 }
@@ -2,11 +2,11 @@
 
 use crate::conn::{Error, Result};
 use futures_util::{
-    io::{AsyncRead, AsyncReadExt, AsyncWrite, ReadHalf},
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadHalf},
     stream::{Stream, TryStreamExt},
 };
 use pin_project::pin_project;
-use std::{convert::TryInto, io};
+use std::{convert::TryInto, io, time::SystemTime};
 use std::{
     pin::Pin,
     task::{Context, Poll},
@@ -23,6 +23,40 @@ pub enum TtyChunk {
     StdErr(Vec<u8>),
 }
 
+impl TtyChunk {
+    /// Returns the number identifying the stream of this chunk on the wire, `0` for stdin, `1`
+    /// for stdout and `2` for stderr.
+    pub const fn stream_index(&self) -> u8 {
+        match self {
+            TtyChunk::StdIn(_) => 0,
+            TtyChunk::StdOut(_) => 1,
+            TtyChunk::StdErr(_) => 2,
+        }
+    }
+
+    /// Converts the data of this chunk to a `String`, replacing invalid UTF-8 sequences with
+    /// `U+FFFD`. Valid UTF-8 is converted without copying.
+    pub fn into_string_lossy(self) -> String {
+        String::from_utf8(self.into())
+            .unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned())
+    }
+
+    /// Returns the data of this chunk as a `&str` if it is valid UTF-8.
+    pub fn to_utf8_str(&self) -> std::result::Result<&str, std::str::Utf8Error> {
+        std::str::from_utf8(self.as_ref())
+    }
+
+    /// Returns a chunk of the same stream containing `data`.
+    #[cfg_attr(not(feature = "chrono"), allow(dead_code))]
+    fn with_data(&self, data: Vec<u8>) -> Self {
+        match self {
+            TtyChunk::StdIn(_) => TtyChunk::StdIn(data),
+            TtyChunk::StdOut(_) => TtyChunk::StdOut(data),
+            TtyChunk::StdErr(_) => TtyChunk::StdErr(data),
+        }
+    }
+}
+
 impl From<TtyChunk> for Vec<u8> {
     fn from(tty_chunk: TtyChunk) -> Self {
         match tty_chunk {
@@ -54,25 +88,89 @@ impl std::ops::DerefMut for TtyChunk {
     }
 }
 
-pub async fn decode_chunk<S>(mut stream: S) -> Option<(Result<TtyChunk>, S)>
+/// Default maximum size of data in a single frame accepted by [`decode`].
+pub const DEFAULT_MAX_FRAME_SIZE: u32 = 16 * 1024 * 1024;
+
+/// Size of the buffers frame data is read into, so that a frame declaring a large size doesn't
+/// cause a large allocation upfront.
+const READ_BUF_SIZE: usize = 64 * 1024;
+
+/// Configuration of [`decode_with_config`].
+#[derive(Debug, Clone)]
+pub struct DecodeConfig {
+    max_frame_size: u32,
+}
+
+impl Default for DecodeConfig {
+    fn default() -> Self {
+        Self {
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+        }
+    }
+}
+
+impl DecodeConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Maximum size of data in a single frame. Frames declaring a larger size fail with
+    /// [`Error::InvalidTtyFrame`].
+    pub fn max_frame_size(mut self, max_frame_size: u32) -> Self {
+        self.max_frame_size = max_frame_size;
+        self
+    }
+}
+
+/// Decodes a single frame from `stream` using the default [`DecodeConfig`].
+pub async fn decode_chunk<S>(stream: S) -> Option<(Result<TtyChunk>, S)>
+where
+    S: AsyncRead + Unpin,
+{
+    decode_chunk_with_config(stream, &DecodeConfig::default()).await
+}
+
+/// Decodes a single frame from `stream`. Frames without any data are skipped.
+pub async fn decode_chunk_with_config<S>(
+    mut stream: S,
+    config: &DecodeConfig,
+) -> Option<(Result<TtyChunk>, S)>
 where
     S: AsyncRead + Unpin,
 {
     let mut header_bytes = [0u8; 8];
 
-    match stream.read_exact(&mut header_bytes).await {
-        Err(e) if e.kind() == futures_util::io::ErrorKind::UnexpectedEof => return None,
-        Err(e) => return Some((Err(Error::IO(e)), stream)),
-        _ => (),
-    }
+    let data_length = loop {
+        match stream.read_exact(&mut header_bytes).await {
+            Err(e) if e.kind() == futures_util::io::ErrorKind::UnexpectedEof => return None,
+            Err(e) => return Some((Err(Error::IO(e)), stream)),
+            _ => (),
+        }
 
-    let size_bytes = &header_bytes[4..];
-    let data_length = u32::from_be_bytes(size_bytes.try_into().ok()?);
+        let size_bytes = &header_bytes[4..];
+        let data_length = u32::from_be_bytes(size_bytes.try_into().ok()?);
+        if data_length != 0 {
+            break data_length;
+        }
+    };
 
-    let mut data = vec![0u8; data_length as usize];
+    if data_length > config.max_frame_size {
+        return Some((
+            Err(Error::InvalidTtyFrame {
+                declared_len: data_length,
+            }),
+            stream,
+        ));
+    }
 
-    if stream.read_exact(&mut data).await.is_err() {
-        return None;
+    let data_length = data_length as usize;
+    let mut data = Vec::with_capacity(data_length.min(READ_BUF_SIZE));
+    while data.len() < data_length {
+        let start = data.len();
+        data.resize(start + (data_length - start).min(READ_BUF_SIZE), 0);
+        if stream.read_exact(&mut data[start..]).await.is_err() {
+            return None;
+        }
     }
 
     let chunk = match header_bytes[0] {
@@ -85,16 +183,136 @@ where
     Some((Ok(chunk), stream))
 }
 
+/// Writes `data` to `writer` as a single frame of the multiplexed stream protocol, prefixed
+/// with an 8 byte header containing the stream number and the big-endian length of `data`.
+pub(crate) async fn write_frame<W>(writer: &mut W, stream: u8, data: &[u8]) -> io::Result<()>
+where
+    W: AsyncWrite + Unpin + ?Sized,
+{
+    let len = u32::try_from(data.len()).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "frame of {} bytes exceeds the maximum frame size",
+                data.len()
+            ),
+        )
+    })?;
+
+    let mut header = [0u8; 8];
+    header[0] = stream;
+    header[4..].copy_from_slice(&len.to_be_bytes());
+
+    writer.write_all(&header).await?;
+    writer.write_all(data).await
+}
+
 /// Decodes a TTY chunk from a stream.
 pub fn decode<S>(hyper_chunk_stream: S) -> impl Stream<Item = Result<TtyChunk>>
+where
+    S: Stream<Item = Result<hyper::body::Bytes>> + Unpin,
+{
+    decode_with_config(hyper_chunk_stream, DecodeConfig::default())
+}
+
+/// Same as [`decode`] but allows configuring the decoder. The stream ends after the first frame
+/// that exceeds the maximum frame size as the position of the next frame is unknown.
+pub fn decode_with_config<S>(
+    hyper_chunk_stream: S,
+    config: DecodeConfig,
+) -> impl Stream<Item = Result<TtyChunk>>
 where
     S: Stream<Item = Result<hyper::body::Bytes>> + Unpin,
 {
     let stream = hyper_chunk_stream
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+        .map_err(io::Error::other)
         .into_async_read();
 
-    futures_util::stream::unfold(stream, decode_chunk)
+    futures_util::stream::unfold(Some((stream, config)), |state| async move {
+        let (stream, config) = state?;
+        match decode_chunk_with_config(stream, &config).await? {
+            (Err(e @ Error::InvalidTtyFrame { .. }), _) => Some((Err(e), None)),
+            (chunk, stream) => Some((chunk, Some((stream, config)))),
+        }
+    })
+}
+
+/// A chunk decoded by [`decode_timestamped`].
+#[derive(Debug, Clone)]
+pub struct TimestampedChunk {
+    /// Position of the chunk in the decoded stream, starting at `0`.
+    pub seq: u64,
+    /// Time the chunk was decoded.
+    pub received_at: SystemTime,
+    pub chunk: TtyChunk,
+}
+
+/// Same as [`decode`] but every chunk is numbered and stamped with the time it was decoded, so
+/// that the order of stdout and stderr chunks can be recovered after they were separated.
+pub fn decode_timestamped<S>(hyper_chunk_stream: S) -> impl Stream<Item = Result<TimestampedChunk>>
+where
+    S: Stream<Item = Result<hyper::body::Bytes>> + Unpin,
+{
+    let mut seq = 0;
+    decode(hyper_chunk_stream).map_ok(move |chunk| {
+        let chunk = TimestampedChunk {
+            seq,
+            received_at: SystemTime::now(),
+            chunk,
+        };
+        seq += 1;
+        chunk
+    })
+}
+
+#[cfg(feature = "chrono")]
+#[cfg_attr(docsrs, doc(cfg(feature = "chrono")))]
+/// A single line of a [`TimestampedChunk`], see [`TimestampedChunk::lines`].
+#[derive(Debug, Clone)]
+pub struct TimestampedLine {
+    /// Sequence number of the chunk containing this line.
+    pub seq: u64,
+    pub received_at: SystemTime,
+    /// Timestamp the daemon prefixed the line with, if any.
+    pub parsed_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// The line, including the trailing newline, without the timestamp.
+    pub chunk: TtyChunk,
+}
+
+impl TimestampedChunk {
+    #[cfg(feature = "chrono")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "chrono")))]
+    /// Splits this chunk into lines and parses the RFC 3339 timestamp the daemon prefixes every
+    /// line with when logs are requested with `timestamps=true`. Lines without a valid
+    /// timestamp are returned unchanged with `parsed_at` set to `None`.
+    pub fn lines(&self) -> Vec<TimestampedLine> {
+        self.chunk
+            .split_inclusive(|b| *b == b'\n')
+            .map(|line| {
+                let (parsed_at, data) = split_timestamp(line);
+                TimestampedLine {
+                    seq: self.seq,
+                    received_at: self.received_at,
+                    parsed_at,
+                    chunk: self.chunk.with_data(data.to_vec()),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(feature = "chrono")]
+/// Splits a `<RFC 3339 timestamp> ` prefix off `line`.
+fn split_timestamp(line: &[u8]) -> (Option<chrono::DateTime<chrono::Utc>>, &[u8]) {
+    let parsed = line.iter().position(|b| *b == b' ').and_then(|pos| {
+        let ts = std::str::from_utf8(&line[..pos]).ok()?;
+        let ts = chrono::DateTime::parse_from_rfc3339(ts).ok()?;
+        Some((ts.with_timezone(&chrono::Utc), &line[pos + 1..]))
+    });
+    match parsed {
+        Some((ts, rest)) => (Some(ts), rest),
+        None => (None, line),
+    }
 }
 
 pub async fn decode_raw<S>(stream: S) -> Option<(Result<TtyChunk>, S)>
@@ -104,7 +322,7 @@ where
     use futures_util::io::AsyncBufReadExt;
     let mut reader = futures_util::io::BufReader::new(stream);
     match reader.fill_buf().await {
-        Ok(buf) if buf.is_empty() => None,
+        Ok([]) => None,
         Ok(buf) => Some((Ok(TtyChunk::StdOut(buf.to_vec())), reader.into_inner())),
         Err(e) if e.kind() == futures_util::io::ErrorKind::UnexpectedEof => None,
         Err(e) => Some((Err(Error::IO(e)), reader.into_inner())),
@@ -124,25 +342,44 @@ pub struct Multiplexer {
 }
 
 impl Multiplexer {
-    pub fn new<Con, F, Fut>(tcp_connection: Con, mut read_fn: F) -> Self
+    pub fn new<Con, F, Fut>(tcp_connection: Con, read_fn: F) -> Self
     where
         Con: AsyncRead + AsyncWrite + Send + 'static,
         F: FnMut(ReadHalf<Con>) -> Fut + Send + 'static,
         Fut: futures_util::Future<Output = Option<(Result<TtyChunk>, ReadHalf<Con>)>>
             + Send
             + 'static,
+    {
+        Self::with_state(tcp_connection, |reader| reader, read_fn)
+    }
+
+    /// Same as [`new`](Multiplexer::new) but `read_fn` is called with a state created by
+    /// `init_state` from the read half of the connection instead of just the read half. Every call
+    /// returns the state passed to the next call, which allows decoders to carry data between
+    /// chunks, like a partially received frame.
+    pub fn with_state<Con, St, I, F, Fut>(tcp_connection: Con, init_state: I, read_fn: F) -> Self
+    where
+        Con: AsyncRead + AsyncWrite + Send + 'static,
+        I: FnOnce(ReadHalf<Con>) -> St,
+        St: Send + 'static,
+        F: FnMut(St) -> Fut + Send + 'static,
+        Fut: futures_util::Future<Output = Option<(Result<TtyChunk>, St)>> + Send + 'static,
     {
         let (reader, writer) = tcp_connection.split();
 
         Self {
-            reader: Box::pin(futures_util::stream::unfold(reader, move |reader| {
-                read_fn(reader)
-            })),
+            reader: Box::pin(futures_util::stream::unfold(init_state(reader), read_fn)),
             writer: Box::pin(writer),
         }
     }
 }
 
+impl std::fmt::Debug for Multiplexer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Multiplexer").finish_non_exhaustive()
+    }
+}
+
 impl Stream for Multiplexer {
     type Item = Result<TtyChunk>;
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
@@ -167,8 +404,257 @@ impl AsyncWrite for Multiplexer {
 }
 
 impl Multiplexer {
+    /// Writes `data` to stdin as a single frame of the multiplexed stream protocol.
+    ///
+    /// Use this when attaching to a container without a TTY allocated, in which case the daemon
+    /// expects every write to stdin to be prefixed with a frame header. With a TTY allocated
+    /// write the raw bytes using the `AsyncWrite` implementation instead.
+    pub async fn write_stdin_framed(&mut self, data: &[u8]) -> io::Result<()> {
+        write_frame(&mut self.writer, 0, data).await
+    }
+
+    /// Shuts down the write half of the connection and closes it, see
+    /// [`UpgradedStream::close`](crate::conn::UpgradedStream::close).
+    pub async fn close(mut self) -> io::Result<()> {
+        self.writer.close().await
+    }
+
     /// Split the `Multiplexer` into the component `Stream` and `AsyncWrite` parts
     pub fn split(self) -> (impl Stream<Item = Result<TtyChunk>>, impl AsyncWrite + Send) {
         (self.reader, self.writer)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        decode, decode_chunk, decode_timestamped, decode_with_config, write_frame, DecodeConfig,
+        TtyChunk,
+    };
+    use crate::conn::Error;
+    use futures_util::{io::Cursor, stream, StreamExt};
+    use hyper::body::Bytes;
+
+    fn frames(frames: &[(u8, &[u8])]) -> Vec<u8> {
+        let mut buf = vec![];
+        for (stream, data) in frames {
+            buf.extend([*stream, 0, 0, 0]);
+            buf.extend((data.len() as u32).to_be_bytes());
+            buf.extend(*data);
+        }
+        buf
+    }
+
+    async fn decode_all(data: Vec<u8>, config: DecodeConfig) -> Vec<crate::conn::Result<TtyChunk>> {
+        let chunks = data
+            .chunks(5)
+            .map(|c| Ok(Bytes::copy_from_slice(c)))
+            .collect::<Vec<_>>();
+        decode_with_config(stream::iter(chunks), config)
+            .collect()
+            .await
+    }
+
+    #[tokio::test]
+    async fn skips_zero_length_frames() {
+        let data = frames(&[(1, b""), (1, b"out"), (2, b""), (2, b"err"), (1, b"")]);
+        let chunks = decode(stream::iter([Ok(Bytes::from(data))]))
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(chunks.len(), 2);
+        assert!(matches!(&chunks[0], Ok(TtyChunk::StdOut(d)) if d == b"out"));
+        assert!(matches!(&chunks[1], Ok(TtyChunk::StdErr(d)) if d == b"err"));
+    }
+
+    #[tokio::test]
+    async fn rejects_oversized_frames() {
+        // a header declaring 4GB of data followed by a valid frame
+        let mut data = vec![1, 0, 0, 0, 0xff, 0xff, 0xff, 0xff];
+        data.extend(frames(&[(1, b"after")]));
+        let chunks = decode_all(data, DecodeConfig::default()).await;
+        assert_eq!(chunks.len(), 1);
+        assert!(matches!(
+            chunks[0],
+            Err(Error::InvalidTtyFrame {
+                declared_len: u32::MAX
+            })
+        ));
+
+        let data = frames(&[(1, b"small"), (1, b"too large")]);
+        let chunks = decode_all(data, DecodeConfig::new().max_frame_size(5)).await;
+        assert_eq!(chunks.len(), 2);
+        assert!(matches!(&chunks[0], Ok(TtyChunk::StdOut(d)) if d == b"small"));
+        assert!(matches!(
+            chunks[1],
+            Err(Error::InvalidTtyFrame { declared_len: 9 })
+        ));
+    }
+
+    #[tokio::test]
+    async fn reads_large_frames_in_parts() {
+        let large = (0..200_000u32).map(|i| i as u8).collect::<Vec<_>>();
+        let chunks = decode_all(frames(&[(2, &large)]), DecodeConfig::default()).await;
+        assert_eq!(chunks.len(), 1);
+        assert!(matches!(&chunks[0], Ok(TtyChunk::StdErr(d)) if *d == large));
+    }
+
+    #[tokio::test]
+    async fn writes_framed_stdin() {
+        let mut cursor = Cursor::new(Vec::new());
+        write_frame(&mut cursor, 0, b"hello").await.unwrap();
+        write_frame(&mut cursor, 0, &[]).await.unwrap();
+
+        let written = cursor.into_inner();
+        assert_eq!(&written[..8], &[0, 0, 0, 0, 0, 0, 0, 5]);
+        assert_eq!(&written[8..13], b"hello");
+        assert_eq!(&written[13..], &[0, 0, 0, 0, 0, 0, 0, 0]);
+
+        let (chunk, _) = decode_chunk(Cursor::new(written)).await.unwrap();
+        assert!(matches!(chunk.unwrap(), TtyChunk::StdIn(data) if data == b"hello"));
+    }
+
+    #[tokio::test]
+    async fn stream_index_matches_wire_format() {
+        for index in 0..=2 {
+            let mut cursor = Cursor::new(Vec::new());
+            write_frame(&mut cursor, index, b"data").await.unwrap();
+            cursor.set_position(0);
+
+            let (chunk, _) = decode_chunk(cursor).await.unwrap();
+            assert_eq!(chunk.unwrap().stream_index(), index);
+        }
+    }
+
+    #[tokio::test]
+    async fn multiplexer_with_stateful_decoder() {
+        use super::Multiplexer;
+        use futures_util::io::{AsyncReadExt, ReadHalf};
+
+        struct State {
+            reader: ReadHalf<Cursor<Vec<u8>>>,
+            buf: Vec<u8>,
+            reads: usize,
+        }
+
+        // reads at most 3 bytes at a time and keeps partial frames in the state
+        async fn read_fn(mut state: State) -> Option<(crate::conn::Result<TtyChunk>, State)> {
+            loop {
+                if state.buf.len() >= 8 {
+                    let len = u32::from_be_bytes(state.buf[4..8].try_into().unwrap()) as usize;
+                    if state.buf.len() >= 8 + len {
+                        let frame = state.buf.drain(..8 + len).collect::<Vec<_>>();
+                        let chunk = match frame[0] {
+                            1 => TtyChunk::StdOut(frame[8..].to_vec()),
+                            _ => TtyChunk::StdErr(frame[8..].to_vec()),
+                        };
+                        return Some((Ok(chunk), state));
+                    }
+                }
+                let mut part = [0u8; 3];
+                let n = state.reader.read(&mut part).await.ok()?;
+                if n == 0 {
+                    return None;
+                }
+                state.reads += 1;
+                state.buf.extend(&part[..n]);
+            }
+        }
+
+        let data = frames(&[(1, b"first frame"), (2, b"err"), (1, b"last")]);
+        let multiplexer = Multiplexer::with_state(
+            Cursor::new(data.clone()),
+            |reader| State {
+                reader,
+                buf: vec![],
+                reads: 0,
+            },
+            read_fn,
+        );
+        assert_eq!(format!("{multiplexer:?}"), "Multiplexer { .. }");
+
+        let chunks = multiplexer
+            .map(|chunk| chunk.unwrap())
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(chunks.len(), 3);
+        assert!(matches!(&chunks[0], TtyChunk::StdOut(d) if d == b"first frame"));
+        assert!(matches!(&chunks[1], TtyChunk::StdErr(d) if d == b"err"));
+        assert!(matches!(&chunks[2], TtyChunk::StdOut(d) if d == b"last"));
+    }
+
+    #[tokio::test]
+    async fn numbers_decoded_chunks() {
+        let data = frames(&[(1, b"out 1\n"), (2, b"err\n"), (1, b"out 2\n")]);
+        let chunks = decode_timestamped(stream::iter(vec![Ok(Bytes::from(data))]))
+            .map(|chunk| chunk.unwrap())
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(chunks.iter().map(|c| c.seq).collect::<Vec<_>>(), [0, 1, 2]);
+        assert!(chunks
+            .windows(2)
+            .all(|w| w[0].received_at <= w[1].received_at));
+        assert!(matches!(&chunks[1].chunk, TtyChunk::StdErr(d) if d == b"err\n"));
+    }
+
+    #[cfg(feature = "chrono")]
+    #[tokio::test]
+    async fn parses_line_timestamps() {
+        use chrono::{TimeZone, Timelike, Utc};
+
+        let data = frames(&[
+            (
+                1,
+                b"2024-03-01T10:00:00.123456789Z first\n2024-03-01T10:00:01Z second\n",
+            ),
+            (2, b"no timestamp here\n"),
+            (1, b"2024-03-01T12:00:02+02:00 last"),
+        ]);
+        let chunks = decode_timestamped(stream::iter(vec![Ok(Bytes::from(data))]))
+            .map(|chunk| chunk.unwrap())
+            .collect::<Vec<_>>()
+            .await;
+
+        let lines = chunks[0].lines();
+        assert_eq!(lines.len(), 2);
+        assert!(lines.iter().all(|line| line.seq == 0));
+        let first = lines[0].parsed_at.unwrap();
+        assert_eq!(
+            first.with_nanosecond(0).unwrap(),
+            Utc.with_ymd_and_hms(2024, 3, 1, 10, 0, 0).unwrap()
+        );
+        assert_eq!(first.nanosecond(), 123456789);
+        assert_eq!(&lines[0].chunk[..], b"first\n");
+        assert_eq!(
+            lines[1].parsed_at,
+            Some(Utc.with_ymd_and_hms(2024, 3, 1, 10, 0, 1).unwrap())
+        );
+        assert_eq!(&lines[1].chunk[..], b"second\n");
+
+        let lines = chunks[1].lines();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].parsed_at, None);
+        assert!(matches!(&lines[0].chunk, TtyChunk::StdErr(d) if d == b"no timestamp here\n"));
+
+        let lines = chunks[2].lines();
+        assert_eq!(lines[0].seq, 2);
+        assert_eq!(
+            lines[0].parsed_at,
+            Some(Utc.with_ymd_and_hms(2024, 3, 1, 10, 0, 2).unwrap())
+        );
+        assert_eq!(&lines[0].chunk[..], b"last");
+    }
+
+    #[test]
+    fn converts_data_to_text() {
+        let chunk = TtyChunk::StdErr("zażółć\n".as_bytes().to_vec());
+        assert_eq!(chunk.to_utf8_str().unwrap(), "zażółć\n");
+        assert_eq!(chunk.stream_index(), 2);
+        assert_eq!(chunk.into_string_lossy(), "zażółć\n");
+
+        let chunk = TtyChunk::StdOut(b"ok \xff\xfe done".to_vec());
+        assert_eq!(chunk.to_utf8_str().unwrap_err().valid_up_to(), 3);
+        assert_eq!(chunk.stream_index(), 1);
+        assert_eq!(chunk.into_string_lossy(), "ok \u{FFFD}\u{FFFD} done");
+    }
+}
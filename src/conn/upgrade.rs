@@ -0,0 +1,280 @@
+use crate::conn::Compat;
+use futures_util::io::{AsyncRead as FuturesAsyncRead, AsyncWrite as FuturesAsyncWrite};
+use hyper::{
+    body::Sender,
+    body::{Bytes, HttpBody},
+    upgrade::Upgraded,
+    Body,
+};
+use std::{
+    future::Future,
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    task::JoinHandle,
+};
+
+/// Content types used by Docker when a hijacked stream is returned with a `200 OK` status
+/// instead of `101 Switching Protocols`.
+pub(crate) const RAW_STREAM_CONTENT_TYPES: &[&str] = &[
+    "application/vnd.docker.raw-stream",
+    "application/vnd.docker.multiplexed-stream",
+];
+
+/// A connection returned from an upgrade request.
+///
+/// When the daemon answers with `101 Switching Protocols` this is the upgraded connection and
+/// works in both directions. When the daemon answers with a `2xx` status instead the stream is
+/// read from the response body and written to the request body through `sender`, the request
+/// is sent with a [`Body::channel`] for that. The underlying connection stays owned by hyper in
+/// that case, once the write half is shut down the request body ends and all further writes
+/// fail with [`io::ErrorKind::BrokenPipe`].
+pub(crate) enum UpgradedConnection {
+    Upgraded(Upgraded),
+    Body {
+        body: Body,
+        buf: Bytes,
+        sender: Option<BodySender>,
+    },
+}
+
+impl UpgradedConnection {
+    pub(crate) fn from_body(body: Body, sender: BodySender) -> Self {
+        Self::Body {
+            body,
+            buf: Bytes::new(),
+            sender: Some(sender),
+        }
+    }
+}
+
+/// The sender of a request body sent through a [`Body::channel`]. The initial body of the
+/// request is forwarded to the channel first, writes are only accepted once it was sent.
+pub(crate) enum BodySender {
+    Forwarding(JoinHandle<Option<Sender>>),
+    Ready(Sender),
+}
+
+impl BodySender {
+    /// Spawns a task streaming `initial` to `sender` chunk by chunk.
+    pub(crate) fn forward(mut initial: Body, mut sender: Sender) -> Self {
+        Self::Forwarding(tokio::spawn(async move {
+            while let Some(chunk) = initial.data().await {
+                match chunk {
+                    Ok(chunk) => sender.send_data(chunk).await.ok()?,
+                    Err(e) => {
+                        log::trace!("failed to read the request body - {e}");
+                        sender.abort();
+                        return None;
+                    }
+                }
+            }
+            Some(sender)
+        }))
+    }
+
+    /// Waits until the initial body was sent and returns the sender.
+    fn poll_sender(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<&mut Sender>> {
+        if let Self::Forwarding(task) = self {
+            match futures_util::ready!(Pin::new(task).poll(cx)) {
+                Ok(Some(sender)) => *self = Self::Ready(sender),
+                _ => {
+                    return Poll::Ready(Err(io::Error::new(
+                        io::ErrorKind::BrokenPipe,
+                        "failed to send the request body",
+                    )))
+                }
+            }
+        }
+        match self {
+            Self::Ready(sender) => Poll::Ready(Ok(sender)),
+            Self::Forwarding(_) => unreachable!("the initial body was forwarded"),
+        }
+    }
+}
+
+impl Future for BodySender {
+    type Output = ();
+
+    /// Completes once the initial body was sent.
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        self.get_mut().poll_sender(cx).map(|_| ())
+    }
+}
+
+fn write_closed() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::BrokenPipe,
+        "the write half of the connection was shut down",
+    )
+}
+
+impl AsyncRead for UpgradedConnection {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        read_buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Upgraded(upgraded) => Pin::new(upgraded).poll_read(cx, read_buf),
+            Self::Body { body, buf, .. } => {
+                while buf.is_empty() {
+                    match Pin::new(&mut *body).poll_data(cx) {
+                        Poll::Ready(Some(Ok(chunk))) => *buf = chunk,
+                        Poll::Ready(Some(Err(e))) => return Poll::Ready(Err(io::Error::other(e))),
+                        Poll::Ready(None) => return Poll::Ready(Ok(())),
+                        Poll::Pending => return Poll::Pending,
+                    }
+                }
+                let n = buf.len().min(read_buf.remaining());
+                read_buf.put_slice(&buf.split_to(n));
+                Poll::Ready(Ok(()))
+            }
+        }
+    }
+}
+
+impl AsyncWrite for UpgradedConnection {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Self::Upgraded(upgraded) => Pin::new(upgraded).poll_write(cx, buf),
+            Self::Body { sender: None, .. } => Poll::Ready(Err(write_closed())),
+            Self::Body {
+                sender: Some(sender),
+                ..
+            } => {
+                let sender = match futures_util::ready!(sender.poll_sender(cx)) {
+                    Ok(sender) => sender,
+                    Err(e) => return Poll::Ready(Err(e)),
+                };
+                if let Err(e) = futures_util::ready!(sender.poll_ready(cx)) {
+                    return Poll::Ready(Err(io::Error::new(io::ErrorKind::BrokenPipe, e)));
+                }
+                match sender.try_send_data(Bytes::copy_from_slice(buf)) {
+                    Ok(()) => Poll::Ready(Ok(buf.len())),
+                    // the channel is full even though it was ready, wait for the next chunk
+                    // to be taken
+                    Err(_) => {
+                        cx.waker().wake_by_ref();
+                        Poll::Pending
+                    }
+                }
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Upgraded(upgraded) => Pin::new(upgraded).poll_flush(cx),
+            Self::Body { .. } => Poll::Ready(Ok(())),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Upgraded(upgraded) => Pin::new(upgraded).poll_shutdown(cx),
+            // dropping the sender ends the request body
+            Self::Body { sender, .. } => {
+                if let Some(body_sender) = sender.as_mut() {
+                    let result = futures_util::ready!(body_sender.poll_sender(cx)).map(|_| ());
+                    sender.take();
+                    return Poll::Ready(result);
+                }
+                Poll::Ready(Ok(()))
+            }
+        }
+    }
+}
+
+/// A stream returned by [`RequestClient::post_upgrade_stream`](crate::conn::RequestClient::post_upgrade_stream).
+///
+/// Dropping the stream shuts down its write half on a best-effort basis before the connection
+/// is closed, the shutdown is skipped if it can't complete immediately, as may happen with TLS.
+/// Use [`close`](UpgradedStream::close) to wait for the shutdown and observe errors.
+pub struct UpgradedStream {
+    inner: Compat<UpgradedConnection>,
+    closed: bool,
+}
+
+impl UpgradedStream {
+    pub(crate) fn new(connection: UpgradedConnection) -> Self {
+        Self {
+            inner: Compat::new(connection),
+            closed: false,
+        }
+    }
+
+    /// Shuts down the write half of the connection, so the daemon sees the end of stdin, and
+    /// closes the connection.
+    ///
+    /// Data the daemon sent that wasn't read is discarded. On TCP connections that makes the
+    /// kernel reset the connection instead of closing it cleanly, so read the stream to the end
+    /// first if the daemon must observe a clean close. This method doesn't do that itself as
+    /// the daemon may keep sending output for as long as the container runs.
+    pub async fn close(mut self) -> io::Result<()> {
+        futures_util::AsyncWriteExt::close(&mut self).await?;
+        self.closed = true;
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for UpgradedStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("UpgradedStream")
+            .field("closed", &self.closed)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for UpgradedStream {
+    fn drop(&mut self) {
+        if self.closed {
+            return;
+        }
+        // The shutdown is polled only once as there is no way to wait in `drop`. On a plain
+        // socket that is enough to send the FIN, but TLS has to write a close_notify first and
+        // may not be ready, in which case the shutdown is skipped.
+        let waker = futures_util::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        match Pin::new(&mut self.inner).poll_close(&mut cx) {
+            Poll::Ready(Ok(())) => {}
+            Poll::Ready(Err(e)) => log::trace!("failed to shut down upgraded connection - {e}"),
+            Poll::Pending => log::trace!("upgraded connection not ready to shut down, skipping"),
+        }
+    }
+}
+
+impl FuturesAsyncRead for UpgradedStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl FuturesAsyncWrite for UpgradedStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+}
@@ -4,6 +4,25 @@ use serde::{Deserialize, Serialize};
 /// Represents unique identifier given to an object upon creation.
 pub struct Id(String);
 
+impl Id {
+    /// Length of a full, non-abbreviated identifier - a hex encoded sha256 digest.
+    pub const FULL_LENGTH: usize = 64;
+
+    /// Length of the identifier in bytes.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns `true` if this is a full identifier rather than a short prefix.
+    pub fn is_full_length(&self) -> bool {
+        self.len() == Self::FULL_LENGTH
+    }
+}
+
 impl From<String> for Id {
     fn from(s: String) -> Self {
         Self(s)
@@ -39,3 +58,25 @@ impl std::fmt::Display for Id {
         write!(f, "{}", self.0)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Id;
+
+    #[test]
+    fn reports_length() {
+        let id = Id::from("");
+        assert!(id.is_empty());
+        assert_eq!(id.len(), 0);
+        assert!(!id.is_full_length());
+
+        let id = Id::from("4bd3a0ca8f0b");
+        assert!(!id.is_empty());
+        assert_eq!(id.len(), 12);
+        assert!(!id.is_full_length());
+
+        let id = Id::from("4bd3a0ca8f0b".repeat(5) + "4bd3");
+        assert_eq!(id.len(), Id::FULL_LENGTH);
+        assert!(id.is_full_length());
+    }
+}
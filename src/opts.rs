@@ -3,27 +3,136 @@ pub trait Filter {
     fn query_item(&self) -> FilterItem;
 }
 
+/// A set of filter items, possibly with different keys. Implemented for every [`Filter`] and for
+/// combinators spanning multiple keys like [`AllOf`].
+pub trait FilterSet {
+    fn query_items(&self) -> Vec<FilterItem>;
+}
+
+impl<F: Filter> FilterSet for F {
+    fn query_items(&self) -> Vec<FilterItem> {
+        vec![self.query_item()]
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+/// Error returned when creating an [`AnyOf`] filter.
+pub enum AnyOfError {
+    #[error("AnyOf requires at least one filter")]
+    Empty,
+    #[error("all filters of AnyOf must use the same key, found `{first}` and `{other}`")]
+    MixedKeys {
+        first: &'static str,
+        other: &'static str,
+    },
+}
+
+/// Matches objects that match any of the inner filters.
+///
+/// All values are encoded under a single key which the daemon interprets as alternatives, for
+/// example `{"status": ["running", "paused"]}`.
+pub struct AnyOf<F: Filter> {
+    key: &'static str,
+    filters: Vec<F>,
+}
+
+impl<F: Filter> AnyOf<F> {
+    /// Creates a filter matching any of `filters`. All filters must use the same key as
+    /// alternatives can't be expressed across different keys.
+    pub fn new(filters: impl IntoIterator<Item = F>) -> Result<Self, AnyOfError> {
+        let filters = filters.into_iter().collect::<Vec<_>>();
+        let first = filters.first().ok_or(AnyOfError::Empty)?.query_item().key();
+        if let Some(other) = filters
+            .iter()
+            .map(|f| f.query_item().key())
+            .find(|key| *key != first)
+        {
+            return Err(AnyOfError::MixedKeys { first, other });
+        }
+        Ok(Self {
+            key: first,
+            filters,
+        })
+    }
+
+    pub fn key(&self) -> &'static str {
+        self.key
+    }
+}
+
+impl<F: Filter> Filter for AnyOf<F> {
+    fn query_item(&self) -> FilterItem {
+        FilterItem::new_multi(
+            self.key,
+            self.filters
+                .iter()
+                .flat_map(|f| f.query_item().into_values()),
+        )
+    }
+}
+
+/// Matches objects that match all of the inner filters.
+///
+/// Every inner filter is encoded under its own key, so filters with different keys are combined
+/// with AND semantics. The daemon treats multiple values of the same key as alternatives, so
+/// inner filters sharing a key are still combined with OR semantics.
+pub struct AllOf<F: FilterSet>(pub Vec<F>);
+
+impl<F: FilterSet> FilterSet for AllOf<F> {
+    fn query_items(&self) -> Vec<FilterItem> {
+        self.0.iter().flat_map(|f| f.query_items()).collect()
+    }
+}
+
+impl Filter for FilterItem {
+    fn query_item(&self) -> FilterItem {
+        FilterItem::new_multi(self.key, self.values.iter().cloned())
+    }
+}
+
+/// A single filter key with one or more values.
 pub struct FilterItem {
     key: &'static str,
-    value: String,
+    values: Vec<String>,
 }
 
 impl FilterItem {
     pub fn new(key: &'static str, value: impl Into<String>) -> Self {
         Self {
             key,
-            value: value.into(),
+            values: vec![value.into()],
+        }
+    }
+
+    /// Creates a filter item with multiple values for the same `key`, for example
+    /// `{"label": ["a=1", "b=2"]}`. The daemon treats the values as alternatives, an object
+    /// matches if it matches any of them.
+    pub fn new_multi(
+        key: &'static str,
+        values: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        Self {
+            key,
+            values: values.into_iter().map(Into::into).collect(),
         }
     }
 
     pub fn key(&self) -> &'static str {
         self.key
     }
+
+    pub fn values(&self) -> &[String] {
+        &self.values
+    }
+
+    pub fn into_values(self) -> Vec<String> {
+        self.values
+    }
 }
 
 impl std::fmt::Display for FilterItem {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.value)
+        write!(f, "{}", self.values.join(","))
     }
 }
 
@@ -81,6 +190,26 @@ macro_rules! impl_vec_field {
     };
 }
 
+#[macro_export]
+/// Implements methods to set a specified parameter that contains a sequence of strings serialized
+/// as JSON.
+macro_rules! impl_str_vec_field {
+    ($(#[doc = $docs:expr])* $name:ident => $param_name:literal) => {
+        paste::item! {
+            $(
+                #[doc= $docs]
+            )*
+            pub fn [< $name  >]<S>(mut self, $name: impl IntoIterator<Item = S>)-> Self
+            where
+                S: Into<String>
+            {
+                self.params.insert($param_name, serde_json::json!($name.into_iter().map(|s| s.into()).collect::<Vec<String>>()));
+                self
+            }
+        }
+    };
+}
+
 #[macro_export]
 /// Implements methods to set a string parameter serialized as JSON.
 macro_rules! impl_str_field {
@@ -167,6 +296,34 @@ macro_rules! impl_url_vec_field {
     };
 }
 
+#[macro_export]
+/// Same as [`impl_url_vec_field`] but the values are deduplicated and sorted before being
+/// stored.
+macro_rules! impl_url_vec_field_dedup {
+    ($(#[doc = $docs:expr])* $name:ident => $param_name:literal) => {
+        paste::item! {
+            $(
+                #[doc= $docs]
+            )*
+            pub fn [< $name >]<S>(mut self, $name: impl IntoIterator<Item = S>)-> Self
+            where
+                S: Into<String>
+            {
+                self.vec_params.insert(
+                    $param_name,
+                    $name
+                        .into_iter()
+                        .map(|s| s.into())
+                        .collect::<std::collections::BTreeSet<String>>()
+                        .into_iter()
+                        .collect(),
+                );
+                self
+            }
+        }
+    };
+}
+
 #[macro_export]
 /// Implements methods to set a urlencoded parameter of a boolean.
 macro_rules! impl_url_bool_field {
@@ -202,12 +359,32 @@ macro_rules! impl_url_enum_field {
 
 #[macro_export]
 /// Implements methods to set a urlencoded squence of key:value items.
+///
+/// The `url` and `json` variants serialize the whole map as a single JSON value while the
+/// `url_pairs` variant repeats the parameter for every pair, like `label=a%3D1&label=b%3D2`.
 macro_rules! impl_map_field {
+    (url_pairs $(#[doc = $docs:expr])* $name:ident => $param_name:literal) => {
+        paste::item! {
+            $(
+                #[doc= $docs]
+            )*
+            pub fn [< $name  >]<K, V>(mut self, $name: impl IntoIterator<Item = (K, V)>)-> Self
+            where
+                K: AsRef<str>,
+                V: AsRef<str>
+            {
+                // stored unencoded like every other parameter, `serialize` encodes them the same
+                // way as `url::encoded_map_pairs`
+                self.vec_params.insert($param_name, $crate::url::map_values($name));
+                self
+            }
+        }
+    };
     (url $(#[doc = $docs:expr])* $name:ident => $param_name:literal) => {
-        impl_map_field! { $(#[doc = $docs])* $name => $param_name => serde_json::to_string(&$name.into_iter().collect::<std::collections::HashMap<_, _>>()).unwrap_or_default() }
+        impl_map_field! { $(#[doc = $docs])* $name => $param_name => serde_json::to_string(&$name.into_iter().collect::<std::collections::BTreeMap<_, _>>()).unwrap_or_default() }
     };
     (json $(#[doc = $docs:expr])* $name:ident => $param_name:literal) => {
-        impl_map_field! { $(#[doc = $docs])* $name => $param_name => serde_json::json!($name.into_iter().collect::<std::collections::HashMap<_, _>>()) }
+        impl_map_field! { $(#[doc = $docs])* $name => $param_name => serde_json::json!($name.into_iter().collect::<std::collections::BTreeMap<_, _>>()) }
     };
     ($(#[doc = $docs:expr])* $name:ident => $param_name:literal => $ret:expr) => {
         paste::item! {
@@ -216,7 +393,7 @@ macro_rules! impl_map_field {
             )*
             pub fn [< $name  >]<K, V>(mut self, $name: impl IntoIterator<Item = (K, V)>)-> Self
             where
-                K: serde::Serialize + Eq + std::hash::Hash,
+                K: serde::Serialize + Ord,
                 V: serde::Serialize
             {
                 self.params.insert($param_name, $ret);
@@ -229,17 +406,20 @@ macro_rules! impl_map_field {
 #[macro_export]
 /// Implements a filter method that uses a [`Filter`](crate::opts::Filter) trait parameter
 macro_rules! impl_filter_func {
-    ($(#[doc = $doc:expr])* $filter_ty:ident) => {
+    ($(#[doc = $doc:expr])* $filter_ty:ty) => {
         $(
             #[doc = $doc]
         )*
         pub fn filter(mut self, filters: impl IntoIterator<Item = $filter_ty>) -> Self
         {
             let mut param = std::collections::BTreeMap::new();
-            for filter_item in filters.into_iter().map(|f| f.query_item()) {
+            for filter_item in filters
+                .into_iter()
+                .flat_map(|f| $crate::opts::FilterSet::query_items(&f))
+            {
                 let key = filter_item.key();
                 let entry_vec = param.entry(key).or_insert(Vec::new());
-                entry_vec.push(filter_item.to_string());
+                entry_vec.extend(filter_item.into_values());
             }
             // structure is a a json encoded object mapping string keys to a list
             // of string values
@@ -273,6 +453,15 @@ macro_rules! impl_url_serialize {
                         Some(serialized)
                     }
                 }
+
+                /// Formats a `curl` command sending a request with `method` to `base_url` with
+                /// these options as the query. Useful for reproducing requests in a shell.
+                // a debugging helper, not expected to be used by every opts type
+                #[allow(dead_code)]
+                pub fn to_curl_command(&self, base_url: &str, method: &str) -> String {
+                    let url = $crate::url::construct_ep(base_url, self.serialize());
+                    $crate::url::curl_command(method, &url)
+                }
             }
         }
     };
@@ -397,6 +586,88 @@ macro_rules! impl_opts_builder {
             $crate::impl_url_serialize!($name);
         }
     };
+    (
+        base_json required_fields => $(#[doc = $docs:expr])* $name:ident,
+        $($(#[doc = $param_docs:expr])* $param:ident: $param_ty:ty => $param_key:literal),+ $(,)?
+    ) => {
+        paste::item! {
+            $(
+                #[doc= $docs]
+            )*
+            #[derive(serde::Serialize, Debug, Clone)]
+            pub struct [< $name Opts >] {
+                pub(crate) params: std::collections::BTreeMap<&'static str, serde_json::Value>,
+                $(
+                    [< $param >]: $param_ty,
+                )+
+            }
+            impl [< $name Opts >] {
+                #[doc = concat!("Returns a new instance of a builder for ", stringify!($name), "Opts.")]
+                $($(
+                    #[doc= $param_docs]
+                )*)+
+                pub fn builder($($param: impl Into<$param_ty>),+) -> [< $name OptsBuilder >] {
+                    [< $name OptsBuilder >]::new($($param),+)
+                }
+
+                pub fn get_param(&self, key: &str) -> Option<&serde_json::Value> {
+                    self.params.get(key)
+                }
+
+                $(
+                    pub fn [< $param >](&self) -> &$param_ty {
+                        &self.$param
+                    }
+                )+
+            }
+
+            #[doc = concat!("A builder struct for ", stringify!($name), "Opts.")]
+            #[derive(Debug, Clone)]
+            pub struct [< $name OptsBuilder >] {
+                pub(crate) params: std::collections::BTreeMap<&'static str, serde_json::Value>,
+                $(
+                    [< $param >]: $param_ty,
+                )+
+            }
+            impl [< $name OptsBuilder >] {
+                #[doc = concat!("A builder struct for ", stringify!($name), "Opts.")]
+                $($(
+                    #[doc= $param_docs]
+                )*)+
+                pub fn new($($param: impl Into<$param_ty>),+) -> Self {
+                    $(
+                        let $param: $param_ty = $param.into();
+                    )+
+                    Self {
+                        params: [$(($param_key, serde_json::json!($param.clone()))),+].into(),
+                        $(
+                            [< $param >]: $param,
+                        )+
+                    }
+                }
+
+                #[doc = concat!("Finish building ", stringify!($name), "Opts.")]
+                pub fn build(self) -> [< $name Opts >] {
+                    [< $name Opts >] {
+                        params: self.params,
+                        $(
+                            [< $param >]: self.$param,
+                        )+
+                    }
+                }
+            }
+        }
+    };
+    (
+        json required_fields => $(#[doc = $docs:expr])* $name:ident,
+        $($(#[doc = $param_docs:expr])* $param:ident: $param_ty:ty => $param_key:literal),+ $(,)?
+    ) => {
+        impl_opts_builder!(
+            base_json required_fields => $(#[doc = $docs])* $name,
+            $($(#[doc = $param_docs])* $param: $param_ty => $param_key),+
+        );
+        $crate::impl_json_serialize!($name);
+    };
 }
 
 #[allow(clippy::crate_in_macro_def)]
@@ -545,6 +816,7 @@ mod test {
             Id(crate::id::Id),
             LabelKey(String),
             LabelKeyVal(String, String),
+            Labels(Vec<String>),
         }
 
         impl Filter for ListFilter {
@@ -554,6 +826,7 @@ mod test {
                     Id(id) => FilterItem::new("id", id.to_string()),
                     LabelKey(key) => FilterItem::new("label", key.clone()),
                     LabelKeyVal(key, val) => FilterItem::new("label", format!("{key}={val}")),
+                    Labels(labels) => FilterItem::new_multi("label", labels.iter().cloned()),
                 }
             }
         }
@@ -577,6 +850,19 @@ mod test {
         let want = Some("filters=%7B%22id%22%3A%5B%22testid%22%5D%2C%22label%22%3A%5B%22test1%22%2C%22test2%3Dkey%22%5D%7D".into());
         let got = opts.serialize();
         assert_eq!(got, want);
+
+        let opts = UrlTestOpts::builder()
+            .filter([
+                ListFilter::Labels(vec!["a=1".into(), "b=2".into()]),
+                ListFilter::LabelKey("c".into()),
+            ])
+            .build();
+
+        let want = Some(crate::url::encoded_pair(
+            "filters",
+            r#"{"label":["a=1","b=2","c"]}"#,
+        ));
+        assert_eq!(opts.serialize(), want);
     }
 
     #[test]
@@ -597,4 +883,280 @@ mod test {
         let got = opts.serialize();
         assert_eq!(got, want);
     }
+
+    #[test]
+    fn url_curl_command() {
+        impl_opts_builder! (url =>
+            UrlTest
+        );
+
+        impl UrlTestOptsBuilder {
+            impl_url_vec_field!(
+                test => "tests"
+            );
+        }
+
+        let base = "http://localhost:2375/containers/json";
+        let opts = UrlTestOpts::builder().test(["it's", "a b"]).build();
+        assert_eq!(
+            opts.to_curl_command(base, "GET"),
+            "curl -X GET 'http://localhost:2375/containers/json?tests=it%27s&tests=a+b'"
+        );
+
+        let opts = UrlTestOpts::builder().build();
+        assert_eq!(
+            opts.to_curl_command(base, "DELETE"),
+            "curl -X DELETE 'http://localhost:2375/containers/json'"
+        );
+    }
+
+    #[test]
+    fn url_vec_dedup_query() {
+        impl_opts_builder! (url =>
+            UrlTest
+        );
+
+        impl UrlTestOptsBuilder {
+            impl_url_vec_field_dedup!(
+                test => "tests"
+            );
+        }
+
+        let opts = UrlTestOpts::builder()
+            .test(["ghi", "abc", "ghi", "def", "abc"])
+            .build();
+
+        let want = Some("tests=abc&tests=def&tests=ghi".into());
+        assert_eq!(opts.serialize(), want);
+    }
+
+    #[test]
+    fn url_map_pairs_query() {
+        impl_opts_builder! (url =>
+            UrlTest
+        );
+
+        impl UrlTestOptsBuilder {
+            impl_map_field!(url_pairs
+                labels => "label"
+            );
+        }
+
+        let opts = UrlTestOpts::builder()
+            .labels([("version", "1=2"), ("app", "a&b")])
+            .build();
+
+        let want = Some("label=app%3Da%26b&label=version%3D1%3D2".into());
+        assert_eq!(opts.serialize(), want);
+        assert_eq!(
+            opts.serialize(),
+            Some(crate::url::encoded_map_pairs(
+                "label",
+                [("version", "1=2"), ("app", "a&b")]
+            ))
+        );
+
+        let opts = UrlTestOpts::builder()
+            .labels(Vec::<(String, String)>::new())
+            .build();
+        assert_eq!(opts.serialize(), None);
+    }
+
+    #[test]
+    fn str_vec_field() {
+        impl_opts_builder! (base_json
+            JsonTest serde_json::Value
+        );
+
+        impl JsonTestOptsBuilder {
+            impl_str_vec_field!(cmd => "Cmd");
+        }
+
+        let opts = JsonTestOpts::builder()
+            .cmd(["sh", "-c"])
+            .cmd(vec![String::from("echo"), String::from("hello")])
+            .build();
+        assert_eq!(
+            serde_json::to_string(&opts.params).unwrap(),
+            r#"{"Cmd":["echo","hello"]}"#
+        );
+
+        let opts = JsonTestOpts::builder().cmd(Vec::<&str>::new()).build();
+        assert_eq!(
+            serde_json::to_string(&opts.params).unwrap(),
+            r#"{"Cmd":[]}"#
+        );
+    }
+
+    #[test]
+    fn required_fields() {
+        impl_opts_builder!(base_json required_fields =>
+            /// Options for creating a network.
+            NetworkCreate,
+            /// Name of the network.
+            name: String => "Name",
+            driver: String => "Driver",
+            internal: bool => "Internal",
+        );
+
+        impl NetworkCreateOptsBuilder {
+            impl_field!(attachable: bool => "Attachable");
+        }
+
+        let opts = NetworkCreateOpts::builder("backend", "bridge", true)
+            .attachable(false)
+            .build();
+        assert_eq!(opts.name(), "backend");
+        assert_eq!(opts.driver(), "bridge");
+        assert!(*opts.internal());
+        assert_eq!(opts.get_param("Driver"), Some(&serde_json::json!("bridge")));
+        assert_eq!(
+            serde_json::to_string(&opts.params).unwrap(),
+            r#"{"Attachable":false,"Driver":"bridge","Internal":true,"Name":"backend"}"#
+        );
+
+        let opts =
+            NetworkCreateOptsBuilder::new(String::from("frontend"), "macvlan", false).build();
+        assert_eq!(opts.get_param("Name"), Some(&serde_json::json!("frontend")));
+    }
+
+    #[test]
+    fn map_field_is_deterministic() {
+        impl_opts_builder! (url =>
+            UrlTest
+        );
+
+        impl UrlTestOptsBuilder {
+            impl_map_field!(url
+                labels => "labels"
+            );
+        }
+
+        impl_opts_builder! (base_json
+            JsonTest serde_json::Value
+        );
+
+        impl JsonTestOptsBuilder {
+            impl_map_field!(json
+                env => "Env"
+            );
+        }
+
+        let pairs = [("zeta", "1"), ("alpha", "2"), ("mid", "3"), ("beta", "4")];
+
+        let want = Some(crate::url::encoded_pair(
+            "labels",
+            r#"{"alpha":"2","beta":"4","mid":"3","zeta":"1"}"#,
+        ));
+        let want_json = r#"{"Env":{"alpha":"2","beta":"4","mid":"3","zeta":"1"}}"#;
+        for _ in 0..100 {
+            let opts = UrlTestOpts::builder().labels(pairs).build();
+            assert_eq!(opts.serialize(), want);
+
+            let opts = JsonTestOpts::builder().env(pairs).build();
+            assert_eq!(serde_json::to_string(&opts.params).unwrap(), want_json);
+        }
+    }
+
+    #[test]
+    fn url_filter_combinators_query() {
+        enum ContainerFilter {
+            Status(&'static str),
+            Label(&'static str),
+        }
+
+        impl Filter for ContainerFilter {
+            fn query_item(&self) -> FilterItem {
+                match self {
+                    ContainerFilter::Status(status) => FilterItem::new("status", *status),
+                    ContainerFilter::Label(label) => FilterItem::new("label", *label),
+                }
+            }
+        }
+
+        impl_opts_builder! (url =>
+            UrlTest
+        );
+
+        impl UrlTestOptsBuilder {
+            impl_filter_func!(AllOf<ContainerFilter>);
+        }
+
+        let opts = UrlTestOpts::builder()
+            .filter([AllOf(vec![
+                ContainerFilter::Label("a=1"),
+                ContainerFilter::Status("running"),
+                ContainerFilter::Label("b"),
+            ])])
+            .build();
+        let want = Some(crate::url::encoded_pair(
+            "filters",
+            r#"{"label":["a=1","b"],"status":["running"]}"#,
+        ));
+        assert_eq!(opts.serialize(), want);
+
+        let any = AnyOf::new([
+            ContainerFilter::Status("running"),
+            ContainerFilter::Status("paused"),
+        ])
+        .unwrap();
+        let item = any.query_item();
+        assert_eq!(item.key(), "status");
+        assert_eq!(item.values(), ["running", "paused"]);
+
+        impl_opts_builder! (url =>
+            AnyTest
+        );
+
+        impl AnyTestOptsBuilder {
+            impl_filter_func!(AnyOf<ContainerFilter>);
+        }
+
+        let opts = AnyTestOpts::builder().filter([any]).build();
+        let want = Some(crate::url::encoded_pair(
+            "filters",
+            r#"{"status":["running","paused"]}"#,
+        ));
+        assert_eq!(opts.serialize(), want);
+
+        assert_eq!(
+            AnyOf::<ContainerFilter>::new([]).err(),
+            Some(AnyOfError::Empty)
+        );
+        assert_eq!(
+            AnyOf::new([
+                ContainerFilter::Status("running"),
+                ContainerFilter::Label("a=1"),
+            ])
+            .err(),
+            Some(AnyOfError::MixedKeys {
+                first: "status",
+                other: "label"
+            })
+        );
+
+        impl_opts_builder! (url =>
+            NestedTest
+        );
+
+        impl NestedTestOptsBuilder {
+            impl_filter_func!(AllOf<AnyOf<ContainerFilter>>);
+        }
+
+        let opts = NestedTestOpts::builder()
+            .filter([AllOf(vec![
+                AnyOf::new([
+                    ContainerFilter::Status("running"),
+                    ContainerFilter::Status("paused"),
+                ])
+                .unwrap(),
+                AnyOf::new([ContainerFilter::Label("a=1")]).unwrap(),
+            ])])
+            .build();
+        let want = Some(crate::url::encoded_pair(
+            "filters",
+            r#"{"label":["a=1"],"status":["running","paused"]}"#,
+        ));
+        assert_eq!(opts.serialize(), want);
+    }
 }
@@ -1,12 +1,17 @@
 //! Utility functions to compression.
 
-use flate2::{write::GzEncoder, Compression};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use futures_util::{Stream, StreamExt};
+use glob::{MatchOptions, Pattern};
+use hyper::body::Bytes;
+use sha2::{Digest, Sha256};
 use std::{
     fs::{self, File},
-    io::{self, Write},
-    path::{Path, MAIN_SEPARATOR},
+    io::{self, BufRead, Read, Write},
+    path::{Component, Path, PathBuf, MAIN_SEPARATOR},
+    time::{SystemTime, UNIX_EPOCH},
 };
-use tar::Builder;
+use tar::{Archive, Builder, EntryType, Header, HeaderMode};
 
 #[cfg(feature = "par-compress")]
 use gzp::{
@@ -14,22 +19,295 @@ use gzp::{
     par::compress::{ParCompress, ParCompressBuilder},
 };
 
+/// Options used to configure how an archive is built by [`dir_with`].
+#[derive(Debug, Default, Clone)]
+pub struct ArchiveOptions {
+    deterministic: bool,
+    fixed_timestamp: Option<u64>,
+}
+
+impl ArchiveOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Zero out modification times and ownership of every entry and drop permission bits that
+    /// depend on the umask so that archives of identical content are identical regardless of
+    /// when and by whom the files were created.
+    pub fn deterministic(mut self, deterministic: bool) -> Self {
+        self.deterministic = deterministic;
+        self
+    }
+
+    /// Set the modification time of every entry to the unix timestamp `ts` instead of the
+    /// modification time of the file, so that archives of identical content built at different
+    /// times are identical. This also applies in [`deterministic`](ArchiveOptions::deterministic)
+    /// mode, which otherwise uses `0`.
+    pub fn with_fixed_timestamp(mut self, ts: u64) -> Self {
+        self.fixed_timestamp = Some(ts);
+        self
+    }
+}
+
 /// Writes a gunzip encoded tarball to `buf` from entries found in `path`.
 pub fn dir<W, P>(buf: W, path: P) -> io::Result<()>
+where
+    W: Write,
+    P: AsRef<Path>,
+{
+    dir_with(buf, path, &ArchiveOptions::default())
+}
+
+/// Same as [`dir`] but allows configuring the archive with `opts`.
+pub fn dir_with<W, P>(buf: W, path: P, opts: &ArchiveOptions) -> io::Result<()>
 where
     W: Write,
     P: AsRef<Path>,
 {
     let encoder = GzEncoder::new(buf, Compression::best());
     let path = path.as_ref();
-    ArchiveBuilder::build(encoder, path)?;
+    ArchiveBuilder::build(encoder, path, opts)?;
+
+    Ok(())
+}
+
+/// Same as [`dir`] but returns the tarball in a new buffer.
+pub fn dir_into_vec<P>(path: P) -> io::Result<Vec<u8>>
+where
+    P: AsRef<Path>,
+{
+    let mut buf = Vec::new();
+    dir(&mut buf, path)?;
+    Ok(buf)
+}
+
+/// Writes a gunzip encoded tarball to `buf` containing only the file at `path`, named after its
+/// file name.
+pub fn file<W, P>(buf: W, path: P) -> io::Result<()>
+where
+    W: Write,
+    P: AsRef<Path>,
+{
+    let encoder = GzEncoder::new(buf, Compression::best());
+    ArchiveBuilder::build_file(encoder, path.as_ref(), &ArchiveOptions::default())?;
+
+    Ok(())
+}
+
+/// Same as [`file`](fn@file) but returns the tarball in a new buffer.
+pub fn file_into_vec<P>(path: P) -> io::Result<Vec<u8>>
+where
+    P: AsRef<Path>,
+{
+    let mut buf = Vec::new();
+    file(&mut buf, path)?;
+    Ok(buf)
+}
+
+/// Same as [`dir`] but skips entries whose path relative to `path` matches one of the glob
+/// patterns in `excludes`, for example `target/**`. A `*` doesn't match a path separator. Contents
+/// of an excluded directory are skipped as well.
+///
+/// Returns an error of kind [`InvalidInput`](io::ErrorKind::InvalidInput) if a pattern is invalid.
+pub fn dir_with_excludes<W, P>(buf: W, path: P, excludes: &[&str]) -> io::Result<()>
+where
+    W: Write,
+    P: AsRef<Path>,
+{
+    let excludes = parse_excludes(excludes)?;
+    let encoder = GzEncoder::new(buf, Compression::best());
+    ArchiveBuilder::build_with_excludes(
+        encoder,
+        path.as_ref(),
+        &ArchiveOptions::default(),
+        excludes,
+    )?;
+
+    Ok(())
+}
+
+fn parse_excludes<S: AsRef<str>>(excludes: &[S]) -> io::Result<Vec<Pattern>> {
+    excludes
+        .iter()
+        .map(|pattern| {
+            let pattern = pattern.as_ref();
+            Pattern::new(pattern).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("invalid exclude pattern `{pattern}` - {e}"),
+                )
+            })
+        })
+        .collect()
+}
+
+/// Options used to configure [`context_summary`].
+#[derive(Debug, Clone)]
+pub struct SummaryOptions {
+    excludes: Vec<String>,
+    largest: usize,
+}
+
+impl Default for SummaryOptions {
+    fn default() -> Self {
+        Self {
+            excludes: Vec::new(),
+            largest: 10,
+        }
+    }
+}
+
+impl SummaryOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Glob patterns of entries to skip, same as the ones passed to [`dir_with_excludes`].
+    pub fn excludes(mut self, excludes: &[&str]) -> Self {
+        self.excludes = excludes.iter().map(|s| s.to_string()).collect();
+        self
+    }
+
+    /// Number of largest files to report, `10` by default.
+    pub fn largest(mut self, n: usize) -> Self {
+        self.largest = n;
+        self
+    }
+}
+
+/// Describes the entries an archive of a directory would contain, see [`context_summary`].
+#[derive(Debug, Default)]
+pub struct ContextSummary {
+    /// Number of regular files.
+    pub files: usize,
+    /// Number of directories.
+    pub directories: usize,
+    /// Total size of all regular files in bytes.
+    pub bytes: u64,
+    /// Paths and sizes of the largest files, largest first.
+    pub largest: Vec<(PathBuf, u64)>,
+    /// Paths skipped because they match one of the excludes. Contents of excluded directories
+    /// are not listed.
+    pub excluded: Vec<PathBuf>,
+    /// Paths that couldn't be read together with the error. Archiving the directory would fail
+    /// on any of them.
+    pub warnings: Vec<(PathBuf, io::Error)>,
+}
+
+/// Walks `path` the same way [`dir_with_excludes`] does without archiving anything and
+/// summarizes what would be archived, for example to warn before sending a large build context.
+/// All paths are relative to `path`.
+///
+/// Entries that can't be read are recorded in [`ContextSummary::warnings`] instead of failing
+/// the walk. Returns an error of kind [`InvalidInput`](io::ErrorKind::InvalidInput) if an exclude
+/// pattern is invalid.
+pub fn context_summary<P>(path: P, opts: &SummaryOptions) -> io::Result<ContextSummary>
+where
+    P: AsRef<Path>,
+{
+    let canonical = path.as_ref().canonicalize()?;
+    let walker = Walker::new(&canonical, parse_excludes(&opts.excludes)?)?;
+    let relative = |path: &Path| path.strip_prefix(&canonical).unwrap_or(path).to_path_buf();
+
+    let mut summary = ContextSummary::default();
+    let mut sizes = Vec::new();
+    walker.walk(&canonical, &mut |entry| {
+        match entry {
+            WalkEntry::Entry(path) => match fs::metadata(path) {
+                Ok(metadata) if metadata.is_dir() => summary.directories += 1,
+                Ok(metadata) => match File::open(path) {
+                    Ok(_) => {
+                        summary.files += 1;
+                        summary.bytes += metadata.len();
+                        sizes.push((relative(path), metadata.len()));
+                    }
+                    Err(e) => summary.warnings.push((relative(path), e)),
+                },
+                Err(e) => summary.warnings.push((relative(path), e)),
+            },
+            WalkEntry::Excluded(path) => summary.excluded.push(relative(path)),
+            WalkEntry::Error(path, e) => summary.warnings.push((relative(path), e)),
+        }
+        Ok(())
+    })?;
+
+    sizes.sort_by(|(a_path, a), (b_path, b)| b.cmp(a).then_with(|| a_path.cmp(b_path)));
+    sizes.truncate(opts.largest);
+    summary.largest = sizes;
+    Ok(summary)
+}
+
+/// Same as [`dir`] but also returns the hex encoded sha256 digest of the data written to
+/// `buf`. To get a digest of an archive built with other options wrap the buffer in a
+/// [`HashingWriter`].
+pub fn dir_with_digest<W, P>(buf: W, path: P) -> io::Result<String>
+where
+    W: Write,
+    P: AsRef<Path>,
+{
+    let mut writer = HashingWriter::new(buf);
+    dir(&mut writer, path)?;
+    Ok(writer.hex_digest())
+}
+
+/// A writer computing the sha256 digest of all data written through it to the inner writer.
+#[derive(Debug, Clone)]
+pub struct HashingWriter<W> {
+    inner: W,
+    hasher: Sha256,
+}
+
+impl<W> HashingWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+        }
+    }
+
+    /// Returns the hex encoded digest of the data written so far.
+    pub fn hex_digest(&self) -> String {
+        format!("{:x}", self.hasher.clone().finalize())
+    }
+
+    /// Returns the inner writer and the hex encoded digest of all data written.
+    pub fn finish(self) -> (W, String) {
+        let digest = self.hex_digest();
+        (self.inner, digest)
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(feature = "zstd")]
+#[cfg_attr(docsrs, doc(cfg(feature = "zstd")))]
+/// Same as [`dir`] but the tarball is compressed with zstd using the specified compression
+/// `level`. Level `0` selects the default level of the zstd library.
+pub fn dir_zstd<W, P>(buf: W, path: P, level: i32) -> io::Result<()>
+where
+    W: Write,
+    P: AsRef<Path>,
+{
+    let encoder = zstd::Encoder::new(buf, level)?;
+    let path = path.as_ref();
+    ArchiveBuilder::build(encoder, path, &ArchiveOptions::default())?.finish()?;
 
     Ok(())
 }
 
 #[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
 #[cfg(feature = "par-compress")]
-/// Same as [`dir`](dir) but initializes the underlying buffer, returns it and utilizes compression
+/// Same as [`dir`] but initializes the underlying buffer, returns it and utilizes compression
 /// parallelization on multiple cores to speed up the work.
 pub fn dir_par<P>(path: P) -> io::Result<Vec<u8>>
 where
@@ -43,7 +321,7 @@ where
     let encoder: ParCompress<Gzip> = ParCompressBuilder::new().from_writer(tx);
 
     let path = path.as_ref();
-    ArchiveBuilder::build(encoder, path)?;
+    ArchiveBuilder::build(encoder, path, &ArchiveOptions::default())?;
 
     rx.rewind()?;
     let mut data = vec![];
@@ -53,7 +331,7 @@ where
 
 #[cfg(not(any(target_os = "linux", target_os = "android", target_os = "freebsd")))]
 #[cfg(feature = "par-compress")]
-/// Same as [`dir`](dir) but initializes the underlying buffer, returns it and utilizes compression
+/// Same as [`dir`] but initializes the underlying buffer, returns it and utilizes compression
 /// parallelization on multiple cores to speed up the work.
 pub fn dir_par<P>(path: P) -> io::Result<Vec<u8>>
 where
@@ -68,7 +346,7 @@ where
     let encoder: ParCompress<Gzip> = ParCompressBuilder::new().from_writer(tx);
 
     let path = path.as_ref();
-    ArchiveBuilder::build(encoder, path)?;
+    ArchiveBuilder::build(encoder, path, &ArchiveOptions::default())?;
 
     let mut rx = std::fs::File::open(&tmp_file_path)?;
     rx.rewind()?;
@@ -77,6 +355,69 @@ where
     Ok(data)
 }
 
+/// Appends `data` to the `archive` as a regular file located at `archive_path` with the
+/// permissions set to `mode` and the modification time set to now.
+///
+/// Useful for adding files that only exist in memory, like a generated `Dockerfile`, to a build
+/// context.
+pub fn append_data<W: Write>(
+    archive: &mut Builder<W>,
+    archive_path: &Path,
+    data: &[u8],
+    mode: u32,
+) -> io::Result<()> {
+    let mut header = Header::new_gnu();
+    header.set_entry_type(EntryType::Regular);
+    header.set_size(data.len() as u64);
+    header.set_mode(mode);
+    header.set_mtime(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default(),
+    );
+    archive.append_data(&mut header, archive_path, data)
+}
+
+/// Verifies that `data` is a well-formed gunzip encoded tarball by reading every entry and
+/// returns the paths of all archived entries.
+///
+/// The returned error describes the index and path of the first corrupt entry.
+pub fn verify(data: &[u8]) -> io::Result<Vec<PathBuf>> {
+    let mut archive = Archive::new(GzDecoder::new(data));
+    let mut paths = vec![];
+
+    for (index, entry) in archive.entries()?.enumerate() {
+        let mut entry = entry.map_err(|e| corrupt_entry(index, None, e))?;
+        let path = entry
+            .path()
+            .map_err(|e| corrupt_entry(index, None, e))?
+            .into_owned();
+        let read = io::copy(&mut entry, &mut io::sink())
+            .map_err(|e| corrupt_entry(index, Some(&path), e))?;
+        if read != entry.size() {
+            let err = io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("expected {} bytes of data, read {read}", entry.size()),
+            );
+            return Err(corrupt_entry(index, Some(&path), err));
+        }
+        paths.push(path);
+    }
+
+    Ok(paths)
+}
+
+fn corrupt_entry(index: usize, path: Option<&Path>, err: io::Error) -> io::Error {
+    let path = path
+        .map(|p| format!(" `{}`", p.display()))
+        .unwrap_or_default();
+    io::Error::new(
+        err.kind(),
+        format!("corrupt archive entry #{index}{path} - {err}"),
+    )
+}
+
 fn resolve_base_path(canonical_path: &Path) -> io::Result<String> {
     let mut base_path_str = canonical_path
         .to_str()
@@ -87,83 +428,538 @@ fn resolve_base_path(canonical_path: &Path) -> io::Result<String> {
             base_path_str.push(MAIN_SEPARATOR)
         }
     }
-    Ok(base_path_str)
+    Ok(normalize_archive_path(&base_path_str))
 }
 
-struct ArchiveBuilder<W: Write> {
+/// Converts a native path string into a tar entry name. Tar archives always use `/` as the
+/// path separator regardless of the platform the archive was created on.
+fn normalize_archive_path(s: &str) -> String {
+    s.replace('\\', "/")
+}
+
+struct ArchiveBuilder<'opts, W: Write> {
     archive: Builder<W>,
-    base_path: String,
+    walker: Walker,
+    opts: &'opts ArchiveOptions,
 }
 
-impl<W: Write> ArchiveBuilder<W> {
-    fn build(buf: W, path: &Path) -> io::Result<()> {
+impl<'opts, W: Write> ArchiveBuilder<'opts, W> {
+    /// Archives `path` into `buf` and returns the flushed writer.
+    fn build(buf: W, path: &Path, opts: &'opts ArchiveOptions) -> io::Result<W> {
+        Self::build_with_excludes(buf, path, opts, Vec::new())
+    }
+
+    /// Same as [`build`](ArchiveBuilder::build) but skips entries whose path relative to `path`
+    /// matches one of `excludes`. Contents of excluded directories are skipped as well.
+    fn build_with_excludes(
+        buf: W,
+        path: &Path,
+        opts: &'opts ArchiveOptions,
+        excludes: Vec<Pattern>,
+    ) -> io::Result<W> {
+        let canonical = path.canonicalize()?;
+        let mut builder = Self::new(buf, &canonical, opts, excludes)?;
+        builder.bundle(&canonical)?;
+        builder.finish()
+    }
+
+    /// Archives the single file at `path` into `buf` and returns the flushed writer.
+    fn build_file(buf: W, path: &Path, opts: &'opts ArchiveOptions) -> io::Result<W> {
         let canonical = path.canonicalize()?;
-        let mut builder = Self::new(buf, &canonical)?;
-        builder.bundle(&canonical, false)?;
-        builder.archive.finish()?;
-        builder.archive.into_inner()?.flush()
+        let parent = canonical.parent().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "path has no parent directory")
+        })?;
+        let mut builder = Self::new(buf, parent, opts, Vec::new())?;
+        append_entry(&mut builder.archive, &builder.walker, opts, &canonical)?;
+        builder.finish()
     }
 
-    fn new(buf: W, canonical: &Path) -> io::Result<Self> {
-        let base_path = resolve_base_path(canonical)?;
+    fn finish(mut self) -> io::Result<W> {
+        self.archive.finish()?;
+        let mut buf = self.archive.into_inner()?;
+        buf.flush()?;
+        Ok(buf)
+    }
 
+    fn new(
+        buf: W,
+        canonical: &Path,
+        opts: &'opts ArchiveOptions,
+        excludes: Vec<Pattern>,
+    ) -> io::Result<Self> {
         Ok(Self {
             archive: Builder::new(buf),
-            base_path,
+            walker: Walker::new(canonical, excludes)?,
+            opts,
         })
     }
 
-    /// Starts the traversal by bundling files/directories in the base path to the archive.
-    fn bundle(&mut self, dir: &Path, bundle_dir: bool) -> io::Result<()> {
-        if fs::metadata(dir)?.is_dir() {
-            if bundle_dir {
-                self.append_entry(dir)?;
+    /// Bundles files/directories found in the base path to the archive.
+    fn bundle(&mut self, dir: &Path) -> io::Result<()> {
+        let Self {
+            archive,
+            walker,
+            opts,
+        } = self;
+        if !fs::metadata(dir)?.is_dir() {
+            return Ok(());
+        }
+        walker.walk(dir, &mut |entry| match entry {
+            WalkEntry::Entry(path) => append_entry(archive, walker, opts, path),
+            WalkEntry::Excluded(_) => Ok(()),
+            WalkEntry::Error(_, err) => Err(err),
+        })
+    }
+}
+
+fn append_entry<W: Write>(
+    archive: &mut Builder<W>,
+    walker: &Walker,
+    opts: &ArchiveOptions,
+    path: &Path,
+) -> io::Result<()> {
+    let canonical = path.canonicalize()?;
+    let relativized = walker.relativize(&canonical)?;
+    let metadata = fs::metadata(&canonical)?;
+    let mut header = entry_header(&metadata, opts);
+    if metadata.is_dir() {
+        archive.append_data(&mut header, Path::new(&relativized), io::empty())
+    } else {
+        archive.append_data(
+            &mut header,
+            Path::new(&relativized),
+            File::open(&canonical)?,
+        )
+    }
+}
+
+/// An entry visited by [`Walker::walk`].
+enum WalkEntry<'a> {
+    /// A file or directory that is part of the archive.
+    Entry(&'a Path),
+    /// A path matching one of the excludes, its contents are not visited.
+    Excluded(&'a Path),
+    /// A path whose metadata or directory listing couldn't be read.
+    Error(&'a Path, io::Error),
+}
+
+/// Traverses a directory tree in a stable order. Used both for building archives and for
+/// [`context_summary`] so that the two always agree on which entries are archived.
+struct Walker {
+    base_path: String,
+    excludes: Vec<Pattern>,
+}
+
+impl Walker {
+    fn new(canonical: &Path, excludes: Vec<Pattern>) -> io::Result<Self> {
+        Ok(Self {
+            base_path: resolve_base_path(canonical)?,
+            excludes,
+        })
+    }
+
+    /// Returns the path of the entry at `canonical` relative to the base path.
+    fn relativize(&self, canonical: &Path) -> io::Result<String> {
+        let normalized = normalize_archive_path(canonical.to_str().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "invalid canonicalized path")
+        })?);
+        Ok(normalized
+            .trim_start_matches(&self.base_path[..])
+            .to_string())
+    }
+
+    fn is_excluded(&self, path: &Path) -> bool {
+        if self.excludes.is_empty() {
+            return false;
+        }
+        let normalized = normalize_archive_path(&path.to_string_lossy());
+        let relativized = normalized.trim_start_matches(&self.base_path[..]);
+        let options = MatchOptions {
+            require_literal_separator: true,
+            ..MatchOptions::new()
+        };
+        self.excludes
+            .iter()
+            .any(|pattern| pattern.matches_with(relativized, options))
+    }
+
+    /// Visits every entry found in `dir` but not `dir` itself. The walk stops at the first
+    /// error returned by `visit`.
+    fn walk<F>(&self, dir: &Path, visit: &mut F) -> io::Result<()>
+    where
+        F: FnMut(WalkEntry<'_>) -> io::Result<()>,
+    {
+        // `read_dir` order depends on the filesystem, sort entries so that archives of
+        // identical trees are identical
+        let entries = fs::read_dir(dir).and_then(|entries| {
+            entries
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<io::Result<Vec<_>>>()
+        });
+        let mut entries = match entries {
+            Ok(entries) => entries,
+            Err(e) => return visit(WalkEntry::Error(dir, e)),
+        };
+        entries.sort();
+        for entry in entries {
+            if self.is_excluded(&entry) {
+                visit(WalkEntry::Excluded(&entry))?;
+                continue;
             }
-            for entry in fs::read_dir(dir)? {
-                let entry = entry?;
-                if fs::metadata(entry.path())?.is_dir() {
-                    self.bundle(&entry.path(), true)?;
-                } else {
-                    self.append_entry(entry.path().as_path())?
+            match fs::metadata(&entry) {
+                Ok(metadata) if metadata.is_dir() => {
+                    visit(WalkEntry::Entry(&entry))?;
+                    self.walk(&entry, visit)?;
                 }
+                Ok(_) => visit(WalkEntry::Entry(&entry))?,
+                Err(e) => visit(WalkEntry::Error(&entry, e))?,
             }
         }
         Ok(())
     }
+}
+
+/// Permission bits kept in deterministic mode. Drops write permission of the group and others,
+/// which depends on the umask, as well as the setuid, setgid and sticky bits.
+const DETERMINISTIC_MODE_MASK: u32 = 0o755;
+
+/// Creates a tar header for an entry described by `metadata` preserving its modification time,
+/// unless a fixed timestamp is set, and, on unix platforms, its permissions and ownership. In
+/// deterministic mode the modification time and ownership are zeroed out and the permissions are
+/// masked with [`DETERMINISTIC_MODE_MASK`].
+fn entry_header(metadata: &fs::Metadata, opts: &ArchiveOptions) -> Header {
+    let mut header = Header::new_gnu();
+    header.set_metadata_in_mode(metadata, HeaderMode::Deterministic);
+    set_permissions(&mut header, metadata);
+    if opts.deterministic {
+        if let Ok(mode) = header.mode() {
+            header.set_mode(mode & DETERMINISTIC_MODE_MASK);
+        }
+        header.set_mtime(opts.fixed_timestamp.unwrap_or(0));
+        header.set_uid(0);
+        header.set_gid(0);
+        return header;
+    }
+    if let Some(ts) = opts.fixed_timestamp {
+        header.set_mtime(ts);
+    } else if let Ok(mtime) = metadata
+        .modified()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).map_err(io::Error::other))
+    {
+        header.set_mtime(mtime.as_secs());
+    }
+    header
+}
 
-    fn append_entry(&mut self, path: &Path) -> io::Result<()> {
-        let canonical = path.canonicalize()?;
-        let relativized = canonical
-            .to_str()
-            .ok_or_else(|| {
-                io::Error::new(io::ErrorKind::InvalidInput, "invalid canonicalized path")
-            })?
-            .trim_start_matches(&self.base_path[..]);
-        if path.is_dir() {
-            self.archive
-                .append_dir(Path::new(relativized), &canonical)?
-        } else {
-            self.archive
-                .append_file(Path::new(relativized), &mut File::open(&canonical)?)?
+#[cfg(unix)]
+fn set_permissions(header: &mut Header, metadata: &fs::Metadata) {
+    use std::os::unix::fs::MetadataExt;
+    header.set_mode(metadata.mode() & 0o7777);
+    header.set_uid(metadata.uid() as u64);
+    header.set_gid(metadata.gid() as u64);
+}
+
+#[cfg(not(unix))]
+fn set_permissions(_: &mut Header, _: &fs::Metadata) {}
+
+/// Options used to configure how an archive is unpacked by [`unpack_with`].
+#[derive(Debug, Default, Clone)]
+pub struct UnpackOptions {
+    strip_components: usize,
+    allow_external_symlinks: bool,
+}
+
+impl UnpackOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of leading path components removed from every entry. Entries with no components
+    /// left are skipped.
+    pub fn strip_components(mut self, n: usize) -> Self {
+        self.strip_components = n;
+        self
+    }
+
+    /// Allow symlinks pointing outside of the destination directory. Entries are never written
+    /// outside of the destination directory regardless of this option.
+    pub fn allow_external_symlinks(mut self, allow: bool) -> Self {
+        self.allow_external_symlinks = allow;
+        self
+    }
+}
+
+/// Describes the result of unpacking an archive.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct UnpackSummary {
+    /// Number of regular files written.
+    pub files: usize,
+    /// Number of directories created.
+    pub directories: usize,
+    /// Number of symbolic and hard links created.
+    pub links: usize,
+    /// Total number of bytes of file data written.
+    pub bytes: u64,
+}
+
+/// Unpacks a tarball received as a stream of chunks, for example from a copy-from-container
+/// endpoint, into `dest`. Gunzip encoded archives are detected and decoded automatically.
+///
+/// Entries with absolute paths or paths containing `..`, as well as symlinks pointing outside of
+/// `dest`, are rejected with an error.
+pub async fn unpack<S>(stream: S, dest: &Path) -> io::Result<UnpackSummary>
+where
+    S: Stream<Item = Result<Bytes, crate::conn::Error>>,
+{
+    unpack_with(stream, dest, UnpackOptions::default()).await
+}
+
+/// Same as [`unpack`] but allows configuring the behaviour with `opts`.
+pub async fn unpack_with<S>(
+    stream: S,
+    dest: &Path,
+    opts: UnpackOptions,
+) -> io::Result<UnpackSummary>
+where
+    S: Stream<Item = Result<Bytes, crate::conn::Error>>,
+{
+    let (tx, rx) = tokio::sync::mpsc::channel(UNPACK_CHANNEL_SIZE);
+    let dest = dest.to_path_buf();
+    let task =
+        tokio::task::spawn_blocking(move || unpack_sync(ChannelReader::new(rx), &dest, &opts));
+
+    futures_util::pin_mut!(stream);
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(io::Error::other);
+        let failed = chunk.is_err();
+        // the receiver is gone once the archive was fully read or unpacking failed
+        if tx.send(chunk).await.is_err() || failed {
+            break;
+        }
+    }
+    drop(tx);
+
+    task.await.map_err(io::Error::other)?
+}
+
+const UNPACK_CHANNEL_SIZE: usize = 16;
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+
+/// Adapts chunks received from an async stream to a blocking reader.
+struct ChannelReader {
+    rx: tokio::sync::mpsc::Receiver<io::Result<Bytes>>,
+    buf: Bytes,
+}
+
+impl ChannelReader {
+    fn new(rx: tokio::sync::mpsc::Receiver<io::Result<Bytes>>) -> Self {
+        Self {
+            rx,
+            buf: Bytes::new(),
+        }
+    }
+}
+
+impl Read for ChannelReader {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        while self.buf.is_empty() {
+            match self.rx.blocking_recv() {
+                Some(Ok(chunk)) => self.buf = chunk,
+                Some(Err(e)) => return Err(e),
+                None => return Ok(0),
+            }
+        }
+        let n = self.buf.len().min(out.len());
+        out[..n].copy_from_slice(&self.buf.split_to(n));
+        Ok(n)
+    }
+}
+
+fn unpack_sync<R: Read>(reader: R, dest: &Path, opts: &UnpackOptions) -> io::Result<UnpackSummary> {
+    let mut reader = io::BufReader::new(reader);
+    if reader.fill_buf()?.starts_with(GZIP_MAGIC) {
+        unpack_archive(Archive::new(GzDecoder::new(reader)), dest, opts)
+    } else {
+        unpack_archive(Archive::new(reader), dest, opts)
+    }
+}
+
+fn unpack_archive<R: Read>(
+    mut archive: Archive<R>,
+    dest: &Path,
+    opts: &UnpackOptions,
+) -> io::Result<UnpackSummary> {
+    fs::create_dir_all(dest)?;
+    let dest = dest.canonicalize()?;
+    let mut summary = UnpackSummary::default();
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        let relative = match sanitize_entry_path(&path, opts.strip_components)? {
+            Some(relative) => relative,
+            None => continue,
+        };
+        let target = dest.join(&relative);
+
+        // nothing may be created before the ancestors are known to be real directories inside
+        // `dest`, `create_dir_all` would follow a symlink extracted earlier
+        check_ancestors(&dest, &relative).map_err(|_| escaping_entry(&path))?;
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+            if !parent.canonicalize()?.starts_with(&dest) {
+                return Err(escaping_entry(&path));
+            }
+        }
+        // an existing symlink at the target would be written through
+        if fs::symlink_metadata(&target).is_ok_and(|m| m.file_type().is_symlink()) {
+            fs::remove_file(&target)?;
+        }
+
+        let entry_type = entry.header().entry_type();
+        if entry_type.is_hard_link() {
+            let link = entry
+                .link_name()?
+                .ok_or_else(|| invalid_entry(&path, "missing link name"))?;
+            let source = sanitize_entry_path(&link, opts.strip_components)?
+                .ok_or_else(|| escaping_entry(&link))?;
+            check_ancestors(&dest, &source).map_err(|_| escaping_entry(&link))?;
+            fs::hard_link(dest.join(source), &target)?;
+            summary.links += 1;
+            continue;
+        }
+        if entry_type.is_symlink() {
+            let link = entry
+                .link_name()?
+                .ok_or_else(|| invalid_entry(&path, "missing link name"))?;
+            let link_dir = target.parent().unwrap_or(&dest);
+            if !opts.allow_external_symlinks && !symlink_resolves_inside(&dest, link_dir, &link)? {
+                return Err(escaping_entry(&path));
+            }
+            summary.links += 1;
+        } else if entry_type.is_dir() {
+            summary.directories += 1;
+        } else if entry_type.is_file() {
+            summary.files += 1;
+            summary.bytes += entry.size();
+        }
+
+        entry.unpack(&target)?;
+    }
+
+    Ok(summary)
+}
+
+/// Returns the path of an entry relative to the destination directory with `strip` leading
+/// components removed or `None` if no components are left.
+fn sanitize_entry_path(path: &Path, strip: usize) -> io::Result<Option<PathBuf>> {
+    let mut sanitized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => sanitized.push(part),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                return Err(escaping_entry(path))
+            }
+        }
+    }
+    let stripped = sanitized.components().skip(strip).collect::<PathBuf>();
+    Ok(Some(stripped).filter(|p| !p.as_os_str().is_empty()))
+}
+
+/// Maximum number of symlinks followed while resolving a symlink target, same as the limit of
+/// Linux.
+const MAX_SYMLINK_HOPS: usize = 40;
+
+/// Fails if an existing ancestor of `relative` within `dest` is a symlink or resolves outside of
+/// `dest`. Ancestors that don't exist yet are fine, they are created as plain directories.
+fn check_ancestors(dest: &Path, relative: &Path) -> io::Result<()> {
+    let mut ancestor = dest.to_path_buf();
+    let parent = relative.parent().unwrap_or_else(|| Path::new(""));
+    for component in parent.components() {
+        ancestor.push(component);
+        let metadata = match fs::symlink_metadata(&ancestor) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        if metadata.file_type().is_symlink() || !ancestor.canonicalize()?.starts_with(dest) {
+            return Err(escaping_entry(relative));
+        }
+    }
+    Ok(())
+}
+
+/// Checks whether a symlink located in `link_dir` pointing to `link` resolves to a location
+/// inside `dest`. Symlinks already extracted to `dest` are followed, so a chain of links that
+/// are each harmless on their own can't point outside.
+fn symlink_resolves_inside(dest: &Path, link_dir: &Path, link: &Path) -> io::Result<bool> {
+    let mut resolved = link_dir.to_path_buf();
+    let mut pending = link
+        .components()
+        .map(|c| PathBuf::from(c.as_os_str()))
+        .collect::<Vec<PathBuf>>();
+    pending.reverse();
+    let mut hops = 0;
+
+    while let Some(next) = pending.pop() {
+        match next.components().next() {
+            Some(Component::Normal(part)) => {
+                let candidate = resolved.join(part);
+                match fs::symlink_metadata(&candidate) {
+                    Ok(metadata) if metadata.file_type().is_symlink() => {
+                        hops += 1;
+                        if hops > MAX_SYMLINK_HOPS {
+                            return Ok(false);
+                        }
+                        let target = fs::read_link(&candidate)?;
+                        pending.extend(
+                            target
+                                .components()
+                                .map(|c| PathBuf::from(c.as_os_str()))
+                                .collect::<Vec<PathBuf>>()
+                                .into_iter()
+                                .rev(),
+                        );
+                    }
+                    _ => resolved = candidate,
+                }
+            }
+            Some(Component::ParentDir) => {
+                if resolved == dest || !resolved.pop() {
+                    return Ok(false);
+                }
+            }
+            Some(Component::CurDir) | None => {}
+            Some(Component::RootDir | Component::Prefix(_)) => return Ok(false),
         }
-        Ok(())
     }
+    Ok(resolved.starts_with(dest))
+}
+
+fn escaping_entry(path: &Path) -> io::Error {
+    invalid_entry(path, "entry escapes the destination directory")
+}
+
+fn invalid_entry(path: &Path, msg: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid archive entry `{}` - {msg}", path.display()),
+    )
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use flate2::read::GzDecoder;
-    use tar::Archive;
     const N_DIRS: usize = 3;
     const N_ENTRIES: usize = 10;
 
     fn _prepare_dirs(tmp: &std::path::Path) {
         for i in 1..=N_DIRS {
-            let d_path = tmp.join(&format!("d{i}"));
+            let d_path = tmp.join(format!("d{i}"));
             std::fs::create_dir(&d_path).unwrap();
             for j in 1..=N_ENTRIES {
-                let f_path = d_path.join(&format!("f{}", i * j));
+                let f_path = d_path.join(format!("f{}", i * j));
                 let mut f = std::fs::File::create(&f_path).unwrap();
                 let _ = f.write(&[j as u8]).unwrap();
                 f.flush().unwrap();
@@ -171,20 +967,25 @@ mod tests {
         }
     }
 
-    fn _verify_archive(buf: &[u8]) {
-        let decoder = GzDecoder::new(buf);
-        let mut archive = Archive::new(decoder);
-
-        let tmp = tempfile::tempdir().unwrap();
-        archive.unpack(tmp.path()).unwrap();
-
+    fn _expected_paths() -> Vec<PathBuf> {
+        let mut paths = vec![];
         for i in 1..=N_DIRS {
-            let d_path = tmp.path().join(&format!("d{i}"));
-            assert!(d_path.exists());
+            let d_path = PathBuf::from(format!("d{i}"));
             for j in 1..=N_ENTRIES {
-                let f_path = d_path.join(&format!("f{}", i * j));
-                assert!(f_path.exists());
+                paths.push(d_path.join(format!("f{}", i * j)));
             }
+            paths.push(d_path);
+        }
+        paths
+    }
+
+    fn _assert_archived(paths: &[PathBuf]) {
+        let paths = paths
+            .iter()
+            .map(|p| p.components().collect::<PathBuf>())
+            .collect::<Vec<_>>();
+        for expected in _expected_paths() {
+            assert!(paths.contains(&expected), "{} missing", expected.display());
         }
     }
 
@@ -194,7 +995,573 @@ mod tests {
         _prepare_dirs(tmp.path());
         let mut buf = vec![];
         dir(&mut buf, tmp.path()).unwrap();
-        _verify_archive(&buf[..]);
+        _assert_archived(&verify(&buf[..]).unwrap());
+    }
+
+    #[test]
+    fn creates_archives_in_vec() {
+        let tmp = tempfile::tempdir().unwrap();
+        _prepare_dirs(tmp.path());
+        _assert_archived(&verify(&dir_into_vec(tmp.path()).unwrap()).unwrap());
+
+        let buf = file_into_vec(tmp.path().join("d2").join("f4")).unwrap();
+        assert_eq!(verify(&buf).unwrap(), [PathBuf::from("f4")]);
+        let mut archive = Archive::new(GzDecoder::new(&buf[..]));
+        let mut entry = archive.entries().unwrap().next().unwrap().unwrap();
+        let mut data = vec![];
+        entry.read_to_end(&mut data).unwrap();
+        assert_eq!(data, [2]);
+    }
+
+    #[test]
+    fn verify_reports_corrupt_entry() {
+        let tmp = tempfile::tempdir().unwrap();
+        _prepare_dirs(tmp.path());
+        let mut buf = vec![];
+        dir(&mut buf, tmp.path()).unwrap();
+
+        // uncompressed archive with the content of the last entry cut off
+        let mut tar = vec![];
+        io::Read::read_to_end(&mut GzDecoder::new(&buf[..]), &mut tar).unwrap();
+        let paths = verify(&buf).unwrap();
+        let last_entry_end = tar.len() - 1024 - 512;
+        let mut encoder = GzEncoder::new(vec![], Compression::fast());
+        encoder.write_all(&tar[..last_entry_end]).unwrap();
+        let corrupt = encoder.finish().unwrap();
+
+        let err = verify(&corrupt).unwrap_err();
+        let msg = err.to_string();
+        assert!(
+            msg.starts_with(&format!(
+                "corrupt archive entry #{} `{}`",
+                paths.len() - 1,
+                paths.last().unwrap().display()
+            )),
+            "{msg}"
+        );
+    }
+
+    #[test]
+    fn normalizes_archive_path() {
+        assert_eq!(
+            normalize_archive_path(r"C:\Users\test\context\d1\f1"),
+            "C:/Users/test/context/d1/f1"
+        );
+        assert_eq!(normalize_archive_path("d1/f1"), "d1/f1");
+
+        let base = normalize_archive_path(r"C:\Users\test\context\");
+        let entry = normalize_archive_path(r"C:\Users\test\context\d1\f1");
+        assert_eq!(entry.trim_start_matches(&base[..]), "d1/f1");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn preserves_permissions() {
+        use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+        let tmp = tempfile::tempdir().unwrap();
+        _prepare_dirs(tmp.path());
+        let d_path = tmp.path().join("d1");
+        let f_path = d_path.join("f1");
+        fs::set_permissions(&f_path, fs::Permissions::from_mode(0o640)).unwrap();
+        fs::set_permissions(&d_path, fs::Permissions::from_mode(0o750)).unwrap();
+
+        let mut buf = vec![];
+        dir(&mut buf, tmp.path()).unwrap();
+
+        let out = tempfile::tempdir().unwrap();
+        let mut archive = Archive::new(GzDecoder::new(&buf[..]));
+        archive.set_preserve_permissions(true);
+        archive.unpack(out.path()).unwrap();
+
+        for path in [Path::new("d1"), Path::new("d1/f1"), Path::new("d2/f2")] {
+            let src = fs::metadata(tmp.path().join(path)).unwrap();
+            let dst = fs::metadata(out.path().join(path)).unwrap();
+            assert_eq!(
+                src.mode() & 0o7777,
+                dst.mode() & 0o7777,
+                "{}",
+                path.display()
+            );
+        }
+        assert_eq!(
+            fs::metadata(out.path().join("d1/f1")).unwrap().mode() & 0o7777,
+            0o640
+        );
+    }
+
+    #[test]
+    fn appends_in_memory_data() {
+        let dockerfile = b"FROM scratch\nCOPY . /\n";
+        let mut archive = Builder::new(vec![]);
+        append_data(
+            &mut archive,
+            Path::new("build/Dockerfile"),
+            dockerfile,
+            0o600,
+        )
+        .unwrap();
+        let buf = archive.into_inner().unwrap();
+
+        let mut archive = Archive::new(&buf[..]);
+        let mut entries = archive.entries().unwrap();
+        let mut entry = entries.next().unwrap().unwrap();
+        assert_eq!(entry.path().unwrap(), Path::new("build/Dockerfile"));
+        assert_eq!(entry.header().mode().unwrap(), 0o600);
+        assert!(entry.header().mtime().unwrap() > 0);
+
+        let mut content = vec![];
+        io::Read::read_to_end(&mut entry, &mut content).unwrap();
+        assert_eq!(content, dockerfile);
+        assert!(entries.next().is_none());
+    }
+
+    fn _chunks(buf: Vec<u8>) -> impl Stream<Item = Result<Bytes, crate::conn::Error>> {
+        let chunks = buf
+            .chunks(100)
+            .map(|c| Ok(Bytes::copy_from_slice(c)))
+            .collect::<Vec<_>>();
+        futures_util::stream::iter(chunks)
+    }
+
+    fn _raw_entry(builder: &mut Builder<Vec<u8>>, path: &[u8], data: &[u8]) {
+        let mut header = Header::new_old();
+        header.as_old_mut().name[..path.len()].copy_from_slice(path);
+        header.set_entry_type(EntryType::Regular);
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append(&header, data).unwrap();
+    }
+
+    #[tokio::test]
+    async fn unpacks_archive_stream() {
+        let tmp = tempfile::tempdir().unwrap();
+        _prepare_dirs(tmp.path());
+        let mut buf = vec![];
+        dir(&mut buf, tmp.path()).unwrap();
+
+        let out = tempfile::tempdir().unwrap();
+        let summary = unpack(_chunks(buf), out.path()).await.unwrap();
+
+        assert_eq!(summary.files, N_DIRS * N_ENTRIES);
+        assert_eq!(summary.directories, N_DIRS);
+        assert_eq!(summary.bytes, (N_DIRS * N_ENTRIES) as u64);
+        for path in _expected_paths() {
+            assert!(out.path().join(&path).exists(), "{}", path.display());
+        }
+    }
+
+    #[tokio::test]
+    async fn unpack_strips_leading_component() {
+        let mut builder = Builder::new(vec![]);
+        append_data(&mut builder, Path::new("root/d1/f1"), b"data", 0o644).unwrap();
+        let buf = builder.into_inner().unwrap();
+
+        let out = tempfile::tempdir().unwrap();
+        let opts = UnpackOptions::new().strip_components(1);
+        let summary = unpack_with(_chunks(buf), out.path(), opts).await.unwrap();
+
+        assert_eq!(summary.files, 1);
+        assert_eq!(fs::read(out.path().join("d1/f1")).unwrap(), b"data");
+        assert!(!out.path().join("root").exists());
+    }
+
+    #[tokio::test]
+    async fn unpack_rejects_escaping_entries() {
+        let mut builder = Builder::new(vec![]);
+        _raw_entry(
+            &mut builder,
+            b"../../etc/passwd",
+            b"root::0:0::/root:/bin/sh",
+        );
+        let buf = builder.into_inner().unwrap();
+
+        let tmp = tempfile::tempdir().unwrap();
+        let out = tmp.path().join("a/b");
+        let err = unpack(_chunks(buf), &out).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(!tmp.path().join("etc/passwd").exists());
+
+        let mut builder = Builder::new(vec![]);
+        _raw_entry(&mut builder, b"/etc/passwd", b"root::0:0::/root:/bin/sh");
+        let buf = builder.into_inner().unwrap();
+        let err = unpack(_chunks(buf), &out).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[tokio::test]
+    #[cfg(unix)]
+    async fn unpack_rejects_external_symlinks() {
+        fn symlink_archive(target: &str) -> Vec<u8> {
+            let mut builder = Builder::new(vec![]);
+            append_data(&mut builder, Path::new("d1/f1"), b"data", 0o644).unwrap();
+            let mut header = Header::new_gnu();
+            header.set_entry_type(EntryType::Symlink);
+            header.set_size(0);
+            builder.append_link(&mut header, "d1/link", target).unwrap();
+            builder.into_inner().unwrap()
+        }
+
+        for target in ["/etc/passwd", "../../outside"] {
+            let out = tempfile::tempdir().unwrap();
+            let err = unpack(_chunks(symlink_archive(target)), out.path())
+                .await
+                .unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{target}");
+
+            let out = tempfile::tempdir().unwrap();
+            let opts = UnpackOptions::new().allow_external_symlinks(true);
+            unpack_with(_chunks(symlink_archive(target)), out.path(), opts)
+                .await
+                .unwrap();
+        }
+
+        let out = tempfile::tempdir().unwrap();
+        let summary = unpack(_chunks(symlink_archive("f1")), out.path())
+            .await
+            .unwrap();
+        assert_eq!(summary.links, 1);
+        assert_eq!(fs::read(out.path().join("d1/link")).unwrap(), b"data");
+    }
+
+    #[tokio::test]
+    #[cfg(unix)]
+    async fn unpack_resolves_symlink_chains() {
+        fn symlink(builder: &mut Builder<Vec<u8>>, path: &str, target: &str) {
+            let mut header = Header::new_gnu();
+            header.set_entry_type(EntryType::Symlink);
+            header.set_size(0);
+            builder.append_link(&mut header, path, target).unwrap();
+        }
+
+        // each link stays inside on its own but `l` resolves to the parent of the destination
+        let mut builder = Builder::new(vec![]);
+        append_data(&mut builder, Path::new("d1/d2/f"), b"data", 0o644).unwrap();
+        symlink(&mut builder, "d1/d2/s", "../..");
+        symlink(&mut builder, "l", "d1/d2/s/..");
+        append_data(&mut builder, Path::new("l/escaped"), b"data", 0o644).unwrap();
+        let buf = builder.into_inner().unwrap();
+
+        let tmp = tempfile::tempdir().unwrap();
+        let out = tmp.path().join("out");
+        let err = unpack(_chunks(buf), &out).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(!tmp.path().join("escaped").exists());
+        assert!(fs::symlink_metadata(out.join("l")).is_err());
+
+        // entries and hard links under an extracted external symlink are rejected before any
+        // directory is created
+        let outside = tmp.path().join("outside");
+        fs::create_dir(&outside).unwrap();
+        let opts = UnpackOptions::new().allow_external_symlinks(true);
+        for entry in ["ext/a/b/f", "ext/f"] {
+            let mut builder = Builder::new(vec![]);
+            symlink(&mut builder, "ext", outside.to_str().unwrap());
+            append_data(&mut builder, Path::new(entry), b"data", 0o644).unwrap();
+            let buf = builder.into_inner().unwrap();
+
+            let out = tmp.path().join(format!("out-{}", entry.len()));
+            let err = unpack_with(_chunks(buf), &out, opts.clone())
+                .await
+                .unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{entry}");
+        }
+        fs::write(outside.join("secret"), "secret").unwrap();
+        let mut builder = Builder::new(vec![]);
+        symlink(&mut builder, "ext", outside.to_str().unwrap());
+        let mut header = Header::new_gnu();
+        header.set_entry_type(EntryType::Link);
+        header.set_size(0);
+        builder
+            .append_link(&mut header, "stolen", "ext/secret")
+            .unwrap();
+        let out = tmp.path().join("out-link");
+        let err = unpack_with(_chunks(builder.into_inner().unwrap()), &out, opts)
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(!out.join("stolen").exists());
+        assert_eq!(fs::read_dir(&outside).unwrap().count(), 1);
+
+        // links resolving inside through other links are fine
+        let mut builder = Builder::new(vec![]);
+        append_data(&mut builder, Path::new("d1/d2/f"), b"data", 0o644).unwrap();
+        symlink(&mut builder, "d1/d2/s", "..");
+        symlink(&mut builder, "l", "d1/d2/s/d2/f");
+        let out = tmp.path().join("out-inside");
+        unpack(_chunks(builder.into_inner().unwrap()), &out)
+            .await
+            .unwrap();
+        assert_eq!(fs::read(out.join("l")).unwrap(), b"data");
+    }
+
+    #[test]
+    fn digest_matches_written_data() {
+        let tmp = tempfile::tempdir().unwrap();
+        _prepare_dirs(tmp.path());
+
+        let mut buf = vec![];
+        let digest = dir_with_digest(&mut buf, tmp.path()).unwrap();
+        _assert_archived(&verify(&buf).unwrap());
+        assert_eq!(digest, format!("{:x}", Sha256::digest(&buf)));
+        assert_eq!(digest.len(), 64);
+    }
+
+    #[test]
+    fn deterministic_archives_have_same_digest() {
+        let tmp = tempfile::tempdir().unwrap();
+        _prepare_dirs(tmp.path());
+        let opts = ArchiveOptions::new().deterministic(true);
+
+        let build = || {
+            let mut writer = HashingWriter::new(vec![]);
+            dir_with(&mut writer, tmp.path(), &opts).unwrap();
+            writer.finish()
+        };
+
+        let (buf, first) = build();
+        let f_path = tmp.path().join("d1").join("f1");
+        File::options()
+            .write(true)
+            .open(&f_path)
+            .unwrap()
+            .set_modified(UNIX_EPOCH + std::time::Duration::from_secs(1_000_000))
+            .unwrap();
+        let (_, second) = build();
+        assert_eq!(first, second);
+
+        let mut archive = Archive::new(GzDecoder::new(&buf[..]));
+        for entry in archive.entries().unwrap() {
+            let entry = entry.unwrap();
+            assert_eq!(entry.header().mtime().unwrap(), 0);
+            assert_eq!(entry.header().uid().unwrap(), 0);
+            assert_eq!(entry.header().gid().unwrap(), 0);
+        }
+    }
+
+    #[test]
+    fn deterministic_archives_are_identical() {
+        let build = |reverse: bool, mode: u32| {
+            let tmp = tempfile::tempdir().unwrap();
+            let mut names = vec!["b", "a", "c"];
+            if reverse {
+                names.reverse();
+            }
+            for name in names {
+                let d_path = tmp.path().join(format!("d{name}"));
+                std::fs::create_dir(&d_path).unwrap();
+                let f_path = d_path.join(format!("f{name}"));
+                std::fs::write(&f_path, name).unwrap();
+                #[cfg(unix)]
+                {
+                    use std::os::unix::fs::PermissionsExt;
+                    std::fs::set_permissions(&f_path, std::fs::Permissions::from_mode(mode))
+                        .unwrap();
+                }
+                std::fs::write(tmp.path().join(name), name).unwrap();
+            }
+            let mut buf = vec![];
+            dir_with(
+                &mut buf,
+                tmp.path(),
+                &ArchiveOptions::new().deterministic(true),
+            )
+            .unwrap();
+            buf
+        };
+
+        let first = build(false, 0o644);
+        assert_eq!(first, build(true, 0o664));
+
+        let paths = verify(&first).unwrap();
+        let expected =
+            ["a", "b", "c", "da", "da/fa", "db", "db/fb", "dc", "dc/fc"].map(PathBuf::from);
+        assert_eq!(paths, expected);
+    }
+
+    #[test]
+    fn fixed_timestamp_overrides_modification_times() {
+        let tmp = tempfile::tempdir().unwrap();
+        _prepare_dirs(tmp.path());
+        let touch = |secs: u64| {
+            let time = UNIX_EPOCH + std::time::Duration::from_secs(secs);
+            for i in 1..=N_DIRS {
+                let d_path = tmp.path().join(format!("d{i}"));
+                for j in 1..=N_ENTRIES {
+                    File::options()
+                        .write(true)
+                        .open(d_path.join(format!("f{}", i * j)))
+                        .unwrap()
+                        .set_modified(time)
+                        .unwrap();
+                }
+            }
+        };
+        let build = |opts: &ArchiveOptions| {
+            let mut buf = vec![];
+            dir_with(&mut buf, tmp.path(), opts).unwrap();
+            buf
+        };
+        let opts = ArchiveOptions::new().with_fixed_timestamp(0);
+
+        touch(1_000_000);
+        let first = build(&opts);
+        let real = build(&ArchiveOptions::new());
+        touch(2_000_000);
+        assert_eq!(first, build(&opts));
+        assert_ne!(real, build(&ArchiveOptions::new()));
+
+        let buf = build(&ArchiveOptions::new().with_fixed_timestamp(1_700_000_000));
+        let mut archive = Archive::new(GzDecoder::new(&buf[..]));
+        for entry in archive.entries().unwrap() {
+            assert_eq!(entry.unwrap().header().mtime().unwrap(), 1_700_000_000);
+        }
+    }
+
+    #[test]
+    fn excludes_matching_entries() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::create_dir_all(root.join("target").join("debug").join("deps")).unwrap();
+        std::fs::write(root.join("src").join("main.rs"), "fn main() {}").unwrap();
+        std::fs::write(root.join("src").join("main.rs.bk"), "").unwrap();
+        std::fs::write(root.join("target").join("debug").join("app"), "").unwrap();
+        std::fs::write(root.join("Cargo.toml"), "").unwrap();
+
+        let mut buf = vec![];
+        dir_with_excludes(&mut buf, root, &["target/**", "*/*.bk"]).unwrap();
+        let paths = verify(&buf)
+            .unwrap()
+            .iter()
+            .map(|p| p.components().collect::<PathBuf>())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            ["Cargo.toml", "src", "src/main.rs", "target"].map(PathBuf::from)
+        );
+
+        let err = dir_with_excludes(vec![], root, &["target/***"]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn summarizes_archived_entries() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::create_dir_all(root.join("target").join("debug")).unwrap();
+        std::fs::create_dir_all(root.join("node_modules").join("left-pad")).unwrap();
+        std::fs::write(root.join("src").join("main.rs"), "fn main() {}").unwrap();
+        std::fs::write(root.join("target").join("debug").join("app"), [0; 64]).unwrap();
+        std::fs::write(
+            root.join("node_modules").join("left-pad").join("index.js"),
+            "",
+        )
+        .unwrap();
+        std::fs::write(root.join("Dockerfile"), "FROM alpine").unwrap();
+        std::fs::write(root.join("Cargo.toml"), "[package]").unwrap();
+        let excludes = ["target/**", "node_modules"];
+
+        let summary =
+            context_summary(root, &SummaryOptions::new().excludes(&excludes).largest(2)).unwrap();
+
+        let mut buf = vec![];
+        dir_with_excludes(&mut buf, root, &excludes).unwrap();
+        let mut archived_bytes = 0;
+        let mut archived_files = 0;
+        let mut archive = Archive::new(GzDecoder::new(&buf[..]));
+        for entry in archive.entries().unwrap() {
+            let entry = entry.unwrap();
+            if entry.header().entry_type().is_file() {
+                archived_files += 1;
+                archived_bytes += entry.size();
+            }
+        }
+        assert_eq!(summary.files, archived_files);
+        assert_eq!(summary.bytes, archived_bytes);
+        assert_eq!(summary.directories, 2);
+        assert_eq!(
+            summary.largest,
+            [
+                (PathBuf::from("src/main.rs"), 12),
+                (PathBuf::from("Dockerfile"), 11)
+            ]
+        );
+        assert_eq!(
+            summary.excluded,
+            ["node_modules", "target/debug"].map(PathBuf::from)
+        );
+        assert!(summary.warnings.is_empty());
+
+        let err = context_summary(root, &SummaryOptions::new().excludes(&["[a-"])).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn summary_records_unreadable_entries() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        std::fs::write(root.join("Dockerfile"), "FROM alpine").unwrap();
+        std::os::unix::fs::symlink(root.join("missing"), root.join("dangling")).unwrap();
+
+        let summary = context_summary(root, &SummaryOptions::new()).unwrap();
+        assert_eq!(summary.files, 1);
+        assert_eq!(summary.warnings.len(), 1);
+        assert_eq!(summary.warnings[0].0, PathBuf::from("dangling"));
+        assert_eq!(summary.warnings[0].1.kind(), io::ErrorKind::NotFound);
+
+        assert!(dir_into_vec(root).is_err());
+    }
+
+    #[test]
+    fn archives_empty_top_level_dirs() {
+        let tmp = tempfile::tempdir().unwrap();
+        std::fs::create_dir(tmp.path().join("empty")).unwrap();
+        std::fs::create_dir_all(tmp.path().join("nested").join("empty")).unwrap();
+
+        let mut buf = vec![];
+        dir(&mut buf, tmp.path()).unwrap();
+        let paths = verify(&buf)
+            .unwrap()
+            .iter()
+            .map(|p| p.components().collect::<PathBuf>())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            [
+                PathBuf::from("empty"),
+                PathBuf::from("nested"),
+                PathBuf::from("nested/empty")
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "zstd")]
+    fn creates_zstd_dir() {
+        let tmp = tempfile::tempdir().unwrap();
+        _prepare_dirs(tmp.path());
+
+        let mut gz_buf = vec![];
+        dir(&mut gz_buf, tmp.path()).unwrap();
+        let mut gz_paths = verify(&gz_buf).unwrap();
+
+        let mut zstd_buf = vec![];
+        dir_zstd(&mut zstd_buf, tmp.path(), 3).unwrap();
+        let mut archive = Archive::new(zstd::Decoder::new(&zstd_buf[..]).unwrap());
+        let mut zstd_paths = archive
+            .entries()
+            .unwrap()
+            .map(|e| e.unwrap().path().unwrap().into_owned())
+            .collect::<Vec<_>>();
+
+        _assert_archived(&zstd_paths);
+        gz_paths.sort();
+        zstd_paths.sort();
+        assert_eq!(gz_paths, zstd_paths);
     }
 
     #[test]
@@ -203,6 +1570,6 @@ mod tests {
         let tmp = tempfile::tempdir().unwrap();
         _prepare_dirs(tmp.path());
         let buf = dir_par(tmp.path()).unwrap();
-        _verify_archive(&buf[..]);
+        _assert_archived(&verify(&buf[..]).unwrap());
     }
 }
@@ -2,9 +2,99 @@
 
 pub use url;
 
-use std::{borrow::Borrow, string::ToString};
+use crate::version::ApiVersion;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use std::{
+    borrow::{Borrow, Cow},
+    collections::BTreeMap,
+    fmt::{self, Write},
+    string::ToString,
+};
 use url::form_urlencoded;
 
+/// A single value of a query parameter.
+///
+/// Booleans and numbers are formatted directly into the query without an intermediate
+/// `String` per value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueryValue<'a> {
+    Str(Cow<'a, str>),
+    Bool(bool),
+    U64(u64),
+    I64(i64),
+    F64(f64),
+}
+
+impl QueryValue<'_> {
+    /// Returns the textual form of this value, using `buf` as scratch space for numbers.
+    fn as_str<'b>(&'b self, buf: &'b mut String) -> &'b str {
+        match self {
+            QueryValue::Str(s) => s,
+            QueryValue::Bool(true) => "true",
+            QueryValue::Bool(false) => "false",
+            _ => {
+                buf.clear();
+                let _ = write!(buf, "{self}");
+                buf
+            }
+        }
+    }
+}
+
+impl fmt::Display for QueryValue<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueryValue::Str(s) => f.write_str(s),
+            QueryValue::Bool(b) => b.fmt(f),
+            QueryValue::U64(n) => n.fmt(f),
+            QueryValue::I64(n) => n.fmt(f),
+            QueryValue::F64(n) => n.fmt(f),
+        }
+    }
+}
+
+impl<'a> From<&'a str> for QueryValue<'a> {
+    fn from(s: &'a str) -> Self {
+        QueryValue::Str(Cow::Borrowed(s))
+    }
+}
+
+impl<'a> From<&'a String> for QueryValue<'a> {
+    fn from(s: &'a String) -> Self {
+        QueryValue::Str(Cow::Borrowed(s))
+    }
+}
+
+impl From<String> for QueryValue<'_> {
+    fn from(s: String) -> Self {
+        QueryValue::Str(Cow::Owned(s))
+    }
+}
+
+impl From<bool> for QueryValue<'_> {
+    fn from(b: bool) -> Self {
+        QueryValue::Bool(b)
+    }
+}
+
+impl From<u64> for QueryValue<'_> {
+    fn from(n: u64) -> Self {
+        QueryValue::U64(n)
+    }
+}
+
+impl From<i64> for QueryValue<'_> {
+    fn from(n: i64) -> Self {
+        QueryValue::I64(n)
+    }
+}
+
+impl From<f64> for QueryValue<'_> {
+    fn from(n: f64) -> Self {
+        QueryValue::F64(n)
+    }
+}
+
 /// Creates an endpoint with a query
 pub fn construct_ep<E, Q>(ep: E, query: Option<Q>) -> String
 where
@@ -18,6 +108,20 @@ where
     ep
 }
 
+/// Creates an endpoint prefixed with `version` with a query, same as
+/// `construct_ep(version.make_endpoint(ep), query)` without an intermediate `String`.
+pub fn construct_versioned_ep<E, Q>(version: ApiVersion, ep: E, query: Option<Q>) -> String
+where
+    E: AsRef<str>,
+    Q: AsRef<str>,
+{
+    let mut ep = version.make_endpoint(ep);
+    if let Some(query) = query {
+        append_query(&mut ep, query);
+    }
+    ep
+}
+
 /// Appends a query to an endpoint
 pub fn append_query<Q>(ep: &mut String, query: Q)
 where
@@ -27,6 +131,157 @@ where
     ep.push_str(query.as_ref());
 }
 
+/// Characters percent-encoded in values bound to a [`Template`], everything except the
+/// unreserved characters of RFC 3986.
+const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// Creates a [`Template`] of an endpoint with `{name}` placeholders.
+///
+/// ```
+/// use containers_api::url::template;
+///
+/// let ep = template("/containers/{id}/exec")
+///     .bind("id", "4bd3")
+///     .query([("detach", "true")])
+///     .build()
+///     .unwrap();
+/// assert_eq!(ep, "/containers/4bd3/exec?detach=true");
+/// ```
+pub fn template(template: &str) -> Template<'_> {
+    Template {
+        template,
+        bindings: Vec::new(),
+        query: None,
+    }
+}
+
+/// An endpoint with `{name}` placeholders replaced by bound values, see [`template`].
+#[derive(Debug, Clone)]
+pub struct Template<'a> {
+    template: &'a str,
+    bindings: Vec<(String, String)>,
+    query: Option<String>,
+}
+
+impl Template<'_> {
+    /// Binds `value` to the placeholder `name`. The value is percent-encoded, including any
+    /// `/` it contains.
+    pub fn bind(mut self, name: impl Into<String>, value: impl fmt::Display) -> Self {
+        let value = value.to_string();
+        self.bindings.push((
+            name.into(),
+            utf8_percent_encode(&value, PATH_SEGMENT).to_string(),
+        ));
+        self
+    }
+
+    /// Appends the key:value `pairs` as a query encoded with [`encoded_pairs`]. Nothing is
+    /// appended if there are no pairs.
+    pub fn query<I, K, V>(mut self, pairs: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Borrow<(K, V)>,
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        let query = encoded_pairs(pairs);
+        self.query = (!query.is_empty()).then_some(query);
+        self
+    }
+
+    /// Returns the endpoint with all placeholders replaced.
+    ///
+    /// Fails if a placeholder is unbound or bound more than once, if a bound name doesn't
+    /// appear in the template or if a placeholder isn't closed.
+    pub fn build(self) -> Result<String, TemplateError> {
+        let err = |kind| TemplateError {
+            template: self.template.to_string(),
+            kind,
+        };
+
+        for (i, (name, _)) in self.bindings.iter().enumerate() {
+            if self.bindings[..i].iter().any(|(n, _)| n == name) {
+                return Err(err(TemplateErrorKind::Duplicate(name.clone())));
+            }
+        }
+
+        let mut ep = String::with_capacity(self.template.len());
+        let mut missing: Vec<String> = Vec::new();
+        let mut used = Vec::new();
+        let mut rest = self.template;
+        while let Some(start) = rest.find('{') {
+            ep.push_str(&rest[..start]);
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| err(TemplateErrorKind::Unclosed))?;
+            let name = &rest[start + 1..start + end];
+            match self.bindings.iter().find(|(n, _)| n == name) {
+                Some((n, value)) => {
+                    ep.push_str(value);
+                    used.push(n);
+                }
+                None if !missing.iter().any(|m| m == name) => missing.push(name.to_string()),
+                None => {}
+            }
+            rest = &rest[start + end + 1..];
+        }
+        ep.push_str(rest);
+
+        if let Some((name, _)) = self.bindings.iter().find(|(n, _)| !used.contains(&n)) {
+            return Err(err(TemplateErrorKind::Unknown(name.clone())));
+        }
+        if !missing.is_empty() {
+            return Err(err(TemplateErrorKind::Unbound(missing)));
+        }
+
+        if let Some(query) = self.query {
+            append_query(&mut ep, query);
+        }
+        Ok(ep)
+    }
+}
+
+/// Error returned by [`Template::build`].
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("invalid endpoint template `{template}` - {kind}")]
+pub struct TemplateError {
+    pub template: String,
+    pub kind: TemplateErrorKind,
+}
+
+/// The reason a [`Template`] couldn't be built.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum TemplateErrorKind {
+    #[error("unbound placeholders: {}", .0.join(", "))]
+    Unbound(Vec<String>),
+    #[error("no placeholder named `{0}`")]
+    Unknown(String),
+    #[error("placeholder `{0}` bound more than once")]
+    Duplicate(String),
+    #[error("unclosed placeholder")]
+    Unclosed,
+}
+
+/// Formats a `curl` command sending a request with `method` to `url`. Both are quoted so that
+/// the command can be pasted directly into a POSIX shell.
+pub fn curl_command(method: &str, url: &str) -> String {
+    let method = if method.chars().all(|c| c.is_ascii_alphabetic()) {
+        method.to_string()
+    } else {
+        shell_quote(method)
+    };
+    format!("curl -X {method} {}", shell_quote(url))
+}
+
+/// Wraps `s` in single quotes escaping any single quotes it contains.
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
 /// Encodes `key` and `val` as urlencoded values.
 pub fn encoded_pair<K, V>(key: K, val: V) -> String
 where
@@ -56,6 +311,34 @@ where
     serializer.finish()
 }
 
+/// Joins every key and value of `map` with a `=`. The returned values are sorted by key, when
+/// a key is repeated the last value wins.
+pub fn map_values<I, K, V>(map: I) -> Vec<String>
+where
+    I: IntoIterator<Item = (K, V)>,
+    K: AsRef<str>,
+    V: AsRef<str>,
+{
+    map.into_iter()
+        .map(|(k, v)| (k.as_ref().to_string(), v.as_ref().to_string()))
+        .collect::<BTreeMap<_, _>>()
+        .into_iter()
+        .map(|(k, v)| format!("{k}={v}"))
+        .collect()
+}
+
+/// Encodes every key:value pair of `map` as a repeated `key` with a urlencoded `k=v` value,
+/// for example `label=a%3D1&label=b%3D2`. Pairs are sorted by key.
+pub fn encoded_map_pairs<Q, I, K, V>(key: Q, map: I) -> String
+where
+    Q: AsRef<str>,
+    I: IntoIterator<Item = (K, V)>,
+    K: AsRef<str>,
+    V: AsRef<str>,
+{
+    encoded_vec_pairs([(key, map_values(map))])
+}
+
 /// Encodes an iterator of key:value pairs as urlencoded values.
 pub fn encoded_pairs<I, K, V>(iter: I) -> String
 where
@@ -64,27 +347,80 @@ where
     K: AsRef<str>,
     V: AsRef<str>,
 {
-    iter.into_iter()
-        .fold(
-            form_urlencoded::Serializer::new(String::new()),
-            |mut acc, v| {
-                let (k, v) = v.borrow();
-                let k = k.as_ref();
-                let v = v.as_ref();
-                if v.is_empty() {
-                    acc.append_key_only(k);
-                } else {
-                    acc.append_pair(k, v);
-                }
-                acc
-            },
-        )
-        .finish()
+    let mut serializer = QuerySerializer::default();
+    iter.into_iter().for_each(|pair| {
+        let (k, v) = pair.borrow();
+        serializer.append(k.as_ref(), v.as_ref().into());
+    });
+    serializer.finish()
+}
+
+/// Encodes an iterator of key:value pairs as urlencoded values skipping every pair without a
+/// value.
+///
+/// ```
+/// use containers_api::url::{encoded_pairs_opt, QueryValue};
+///
+/// let query = encoded_pairs_opt([
+///     ("all", Some(QueryValue::from(true))),
+///     ("limit", Some(10u64.into())),
+///     ("filter", None),
+/// ]);
+/// assert_eq!(query, "all=true&limit=10");
+/// ```
+pub fn encoded_pairs_opt<'a, I, K, V>(iter: I) -> String
+where
+    I: IntoIterator<Item = (K, Option<V>)>,
+    K: AsRef<str>,
+    V: Into<QueryValue<'a>>,
+{
+    let mut serializer = QuerySerializer::default();
+    iter.into_iter().for_each(|(k, v)| {
+        if let Some(v) = v {
+            serializer.append(k.as_ref(), v.into());
+        }
+    });
+    serializer.finish()
+}
+
+struct QuerySerializer {
+    inner: form_urlencoded::Serializer<'static, String>,
+    buf: String,
+}
+
+impl Default for QuerySerializer {
+    fn default() -> Self {
+        Self {
+            inner: form_urlencoded::Serializer::new(String::new()),
+            buf: String::new(),
+        }
+    }
+}
+
+impl QuerySerializer {
+    /// Appends `key` with `val`, empty values are appended as a key only.
+    fn append(&mut self, key: &str, val: QueryValue<'_>) {
+        let val = val.as_str(&mut self.buf);
+        if val.is_empty() {
+            self.inner.append_key_only(key);
+        } else {
+            self.inner.append_pair(key, val);
+        }
+    }
+
+    fn finish(mut self) -> String {
+        self.inner.finish()
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{append_query, construct_ep, encoded_pair, encoded_pairs, encoded_vec_pairs};
+    use super::{
+        append_query, construct_ep, construct_versioned_ep, curl_command, encoded_map_pairs,
+        encoded_pair, encoded_pairs, encoded_pairs_opt, encoded_vec_pairs, template, QueryValue,
+        TemplateErrorKind,
+    };
+    use url::form_urlencoded;
 
     #[test]
     fn appends_query() {
@@ -104,6 +440,88 @@ mod tests {
         assert_eq!(construct_ep(ep, Some(query)), want);
     }
 
+    #[test]
+    fn builds_templates() {
+        let ep = template("/containers/{id}/archive")
+            .bind("id", "my container/1")
+            .query([("path", "/etc/hosts")])
+            .build()
+            .unwrap();
+        assert_eq!(
+            ep,
+            "/containers/my%20container%2F1/archive?path=%2Fetc%2Fhosts"
+        );
+
+        let ep = template("/networks/{net}/{net}")
+            .bind("net", "a&b?c#d")
+            .query::<[(&str, &str); 0], _, _>([])
+            .build()
+            .unwrap();
+        assert_eq!(ep, "/networks/a%26b%3Fc%23d/a%26b%3Fc%23d");
+
+        let kind = |t: super::Template| t.build().unwrap_err().kind;
+        assert_eq!(
+            kind(template("/images/{name}/tag/{tag}/{name}")),
+            TemplateErrorKind::Unbound(vec!["name".into(), "tag".into()])
+        );
+        assert_eq!(
+            kind(
+                template("/images/{name}/json")
+                    .bind("name", "a")
+                    .bind("name", "b")
+            ),
+            TemplateErrorKind::Duplicate("name".into())
+        );
+        assert_eq!(
+            kind(
+                template("/images/{name}/json")
+                    .bind("name", "a")
+                    .bind("id", "b")
+            ),
+            TemplateErrorKind::Unknown("id".into())
+        );
+        assert_eq!(
+            kind(template("/images/{name/json")),
+            TemplateErrorKind::Unclosed
+        );
+
+        let err = template("/exec/{id}/start").build().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid endpoint template `/exec/{id}/start` - unbound placeholders: id"
+        );
+    }
+
+    #[test]
+    fn constructs_versioned_endpoint() {
+        let version = crate::version::ApiVersion::new(1, Some(41), None);
+        for ep in ["/containers/json", "containers/json"] {
+            for query in [None, Some("all=true&limit=5")] {
+                assert_eq!(
+                    construct_versioned_ep(version, ep, query),
+                    construct_ep(version.make_endpoint(ep), query)
+                );
+            }
+        }
+        assert_eq!(
+            construct_versioned_ep(version, "/containers/json", Some("all=true")),
+            "/v1.41/containers/json?all=true"
+        );
+    }
+
+    #[test]
+    fn formats_curl_command() {
+        assert_eq!(
+            curl_command("GET", "http://localhost/containers/json?all=true"),
+            "curl -X GET 'http://localhost/containers/json?all=true'"
+        );
+        assert_eq!(
+            curl_command("POST", "http://it's/a'b"),
+            r"curl -X POST 'http://it'\''s/a'\''b'"
+        );
+        assert_eq!(curl_command("GET; rm", "x"), "curl -X 'GET; rm' 'x'");
+    }
+
     #[test]
     fn encodes_pair() {
         let key = "lang";
@@ -129,4 +547,73 @@ mod tests {
         let want = "lang=en&lang=pl%26&id=1337&country=xxx&country=yyy&country=zzz";
         assert_eq!(encoded_vec_pairs(pairs), want);
     }
+
+    #[test]
+    fn encodes_map_pairs() {
+        let map = [("b", "2&3"), ("a", "x=y")];
+        let want = "label=a%3Dx%3Dy&label=b%3D2%263";
+        assert_eq!(encoded_map_pairs("label", map), want);
+
+        let empty: [(&str, &str); 0] = [];
+        assert_eq!(encoded_map_pairs("label", empty), "");
+    }
+
+    #[test]
+    fn encodes_pairs_opt() {
+        let pairs: [(&str, Option<QueryValue>); 7] = [
+            ("all", Some(true.into())),
+            ("size", Some(false.into())),
+            ("limit", Some(10u64.into())),
+            ("since", Some((-5i64).into())),
+            ("ratio", Some(0.5.into())),
+            ("filter", None),
+            ("name", Some("a b&c".into())),
+        ];
+        let want = "all=true&size=false&limit=10&since=-5&ratio=0.5&name=a+b%26c";
+        assert_eq!(encoded_pairs_opt(pairs), want);
+
+        let pairs = [("dangling", Some("")), ("skipped", None), ("id", Some("1"))];
+        assert_eq!(encoded_pairs_opt(pairs), "dangling&id=1");
+    }
+
+    #[test]
+    fn encoded_pairs_match_form_urlencoded() {
+        const CHARS: &[char] = &['a', 'Z', '0', ' ', '&', '=', '+', '%', '?', '/', 'ł', '🦀'];
+
+        // simple deterministic generator, good enough to cover many combinations
+        let mut seed = 0x2545_f491_u64;
+        let mut next = move |max: usize| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            (seed % max as u64) as usize
+        };
+
+        for _ in 0..500 {
+            let pairs = (0..next(5))
+                .map(|_| {
+                    let mut gen = || (0..next(6)).map(|_| CHARS[next(CHARS.len())]).collect();
+                    let k: String = gen();
+                    let v: String = gen();
+                    (k, v)
+                })
+                .collect::<Vec<_>>();
+
+            let mut want = form_urlencoded::Serializer::new(String::new());
+            for (k, v) in &pairs {
+                if v.is_empty() {
+                    want.append_key_only(k);
+                } else {
+                    want.append_pair(k, v);
+                }
+            }
+            let want = want.finish();
+
+            assert_eq!(encoded_pairs(&pairs), want);
+            assert_eq!(
+                encoded_pairs_opt(pairs.iter().map(|(k, v)| (k, Some(v)))),
+                want
+            );
+        }
+    }
 }
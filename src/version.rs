@@ -15,6 +15,44 @@ pub struct ApiVersion {
     patch: Option<usize>,
 }
 
+/// Docker API version of Docker Engine 19.03.
+pub const DOCKER_V1_40: ApiVersion = ApiVersion::new(1, Some(40), None);
+/// Docker API version of Docker Engine 20.10.
+pub const DOCKER_V1_41: ApiVersion = ApiVersion::new(1, Some(41), None);
+/// Docker API version of Docker Engine 23.0.
+pub const DOCKER_V1_42: ApiVersion = ApiVersion::new(1, Some(42), None);
+/// Docker API version of Docker Engine 24.0.
+pub const DOCKER_V1_43: ApiVersion = ApiVersion::new(1, Some(43), None);
+/// Docker API version of Docker Engine 25.0.
+pub const DOCKER_V1_44: ApiVersion = ApiVersion::new(1, Some(44), None);
+/// Docker API version of Docker Engine 26.0.
+pub const DOCKER_V1_45: ApiVersion = ApiVersion::new(1, Some(45), None);
+/// Docker API version of Docker Engine 27.0.
+pub const DOCKER_V1_46: ApiVersion = ApiVersion::new(1, Some(46), None);
+/// Docker API version of Docker Engine 27.2.
+pub const DOCKER_V1_47: ApiVersion = ApiVersion::new(1, Some(47), None);
+/// Docker API version of Docker Engine 28.0.
+pub const DOCKER_V1_48: ApiVersion = ApiVersion::new(1, Some(48), None);
+/// Docker API version of Docker Engine 28.1.
+pub const DOCKER_V1_49: ApiVersion = ApiVersion::new(1, Some(49), None);
+/// Docker API version of Docker Engine 28.2.
+pub const DOCKER_V1_50: ApiVersion = ApiVersion::new(1, Some(50), None);
+/// Docker API version of Docker Engine 28.3.
+pub const DOCKER_V1_51: ApiVersion = ApiVersion::new(1, Some(51), None);
+/// Docker API version of Docker Engine 29.0.
+pub const DOCKER_V1_52: ApiVersion = ApiVersion::new(1, Some(52), None);
+/// The latest Docker API version known to this crate.
+pub const LATEST_DOCKER: ApiVersion = DOCKER_V1_52;
+
+/// Libpod API version of Podman 3.0.
+pub const PODMAN_V3_0: ApiVersion = ApiVersion::new(3, Some(0), None);
+/// Libpod API version of Podman 4.0.
+pub const PODMAN_V4_0: ApiVersion = ApiVersion::new(4, Some(0), None);
+/// Libpod API version of Podman 5.0.
+pub const PODMAN_V5_0: ApiVersion = ApiVersion::new(5, Some(0), None);
+/// The latest Libpod API version known to this crate.
+pub const LATEST_PODMAN: ApiVersion = PODMAN_V5_0;
+
 impl ApiVersion {
     pub const fn new(major: usize, minor: Option<usize>, patch: Option<usize>) -> Self {
         Self {
@@ -37,13 +75,24 @@ impl ApiVersion {
     }
 
     pub fn make_endpoint(&self, ep: impl AsRef<str>) -> String {
-        let ep = ep.as_ref();
-        format!(
-            "/v{}{}{}",
-            self,
-            if !ep.starts_with('/') { "/" } else { "" },
-            ep
-        )
+        self.make_endpoint_with(format_args!("{}", ep.as_ref()))
+    }
+
+    /// Same as [`make_endpoint`](ApiVersion::make_endpoint) but formats the endpoint directly
+    /// into the returned path, for example
+    /// `version.make_endpoint_with(format_args!("/containers/{id}/start"))`.
+    pub fn make_endpoint_with(&self, args: std::fmt::Arguments<'_>) -> String {
+        use std::fmt::Write;
+
+        let mut ep = String::new();
+        // writing to a String never fails
+        let _ = write!(ep, "/v{self}");
+        let start = ep.len();
+        let _ = ep.write_fmt(args);
+        if !ep[start..].starts_with('/') {
+            ep.insert(start, '/');
+        }
+        ep
     }
 }
 
@@ -120,3 +169,72 @@ impl FromStr for ApiVersion {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        ApiVersion, DOCKER_V1_40, DOCKER_V1_41, DOCKER_V1_42, DOCKER_V1_43, DOCKER_V1_44,
+        DOCKER_V1_45, DOCKER_V1_46, DOCKER_V1_47, DOCKER_V1_48, DOCKER_V1_49, DOCKER_V1_50,
+        DOCKER_V1_51, DOCKER_V1_52, LATEST_DOCKER, LATEST_PODMAN, PODMAN_V3_0, PODMAN_V4_0,
+        PODMAN_V5_0,
+    };
+
+    #[test]
+    fn makes_same_endpoint_from_arguments() {
+        let id = "4bd3a4f5";
+        for version in [
+            ApiVersion::from(1),
+            ApiVersion::from((1, 41)),
+            ApiVersion::from((4, 0, 2)),
+        ] {
+            assert_eq!(
+                version.make_endpoint_with(format_args!("/containers/{id}/start")),
+                version.make_endpoint(format!("/containers/{id}/start"))
+            );
+            assert_eq!(
+                version.make_endpoint_with(format_args!("containers/{id}/start")),
+                version.make_endpoint(format!("containers/{id}/start"))
+            );
+            assert_eq!(
+                version.make_endpoint_with(format_args!("")),
+                version.make_endpoint("")
+            );
+        }
+        assert_eq!(
+            ApiVersion::from((1, 41)).make_endpoint_with(format_args!("containers/{id}/start")),
+            "/v1.41/containers/4bd3a4f5/start"
+        );
+    }
+
+    #[test]
+    fn well_known_versions_are_ordered() {
+        let docker = [
+            DOCKER_V1_40,
+            DOCKER_V1_41,
+            DOCKER_V1_42,
+            DOCKER_V1_43,
+            DOCKER_V1_44,
+            DOCKER_V1_45,
+            DOCKER_V1_46,
+            DOCKER_V1_47,
+            DOCKER_V1_48,
+            DOCKER_V1_49,
+            DOCKER_V1_50,
+            DOCKER_V1_51,
+            DOCKER_V1_52,
+        ];
+        assert!(docker.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(docker.iter().max(), Some(&LATEST_DOCKER));
+
+        let podman = [PODMAN_V3_0, PODMAN_V4_0, PODMAN_V5_0];
+        assert!(podman.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(podman.iter().max(), Some(&LATEST_PODMAN));
+
+        assert_eq!("1.44".parse::<ApiVersion>().unwrap(), DOCKER_V1_44);
+        assert_eq!(DOCKER_V1_41.make_endpoint("/info"), "/v1.41/info");
+        assert_eq!(
+            PODMAN_V4_0.make_endpoint("/libpod/info"),
+            "/v4.0/libpod/info"
+        );
+    }
+}